@@ -0,0 +1,74 @@
+//! Throughput of reading a download on blocking threads
+//!
+//! Compares a `spawn_blocking` with a new buffer per chunk, as downloads were read before,
+//! with `BlockingReader`. Run with `cargo bench --features bench --bench reader`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use down_on_spot::BlockingReader;
+use std::hint::black_box;
+use std::io::{Cursor, Read};
+use tokio::runtime::Runtime;
+
+/// Size of the simulated download
+const SIZE: usize = 16 * 1024 * 1024;
+/// Chunk size of the previous reader
+const CHUNK_SIZE: usize = 1024 * 64;
+
+/// Previous reader, a blocking task and a new buffer for every chunk
+async fn spawn_per_chunk(mut read: Cursor<Vec<u8>>) -> usize {
+	let mut total = 0;
+	loop {
+		let (r, size, buf) = tokio::task::spawn_blocking(move || {
+			let mut buf = vec![0; CHUNK_SIZE];
+			let size = read.read(&mut buf).unwrap();
+			(read, size, buf)
+		})
+		.await
+		.unwrap();
+		read = r;
+		if size == 0 {
+			break;
+		}
+		total += black_box(&buf[..size]).len();
+	}
+	total
+}
+
+/// Single blocking thread with reused buffers
+async fn blocking_reader(read: Cursor<Vec<u8>>) -> usize {
+	let mut reader = BlockingReader::new(read);
+	let mut total = 0;
+	while let Some(buf) = reader.next().await {
+		let buf = buf.unwrap();
+		total += black_box(&buf).len();
+		reader.recycle(buf);
+	}
+	reader.finish().await.unwrap();
+	total
+}
+
+fn reader(c: &mut Criterion) {
+	let runtime = Runtime::new().unwrap();
+	let data = vec![0u8; SIZE];
+
+	let mut group = c.benchmark_group("reader");
+	group.throughput(Throughput::Bytes(SIZE as u64));
+	group.bench_function("spawn_blocking per chunk", |b| {
+		b.to_async(&runtime).iter_batched(
+			|| Cursor::new(data.clone()),
+			spawn_per_chunk,
+			BatchSize::LargeInput,
+		)
+	});
+	group.bench_function("BlockingReader", |b| {
+		b.to_async(&runtime).iter_batched(
+			|| Cursor::new(data.clone()),
+			blocking_reader,
+			BatchSize::LargeInput,
+		)
+	});
+	group.finish();
+}
+
+criterion_group!(benches, reader);
+criterion_main!(benches);
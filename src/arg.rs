@@ -1,12 +1,70 @@
 use crate::settings;
-use clap::{crate_authors, crate_version, Args as ClapArgs, Command, FromArgMatches, Parser};
+use clap::{
+	crate_authors, crate_version, Args as ClapArgs, Command, FromArgMatches, Parser, Subcommand,
+	ValueEnum,
+};
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, arg_required_else_help = true)]
 pub struct Args {
+	#[command(subcommand)]
+	pub command: Option<Commands>,
+
 	#[arg(
 		long_help = "Track / Album / Playlist / Artist / Podcast / Episode / Show / User URL, ID or search term\nFor example, \'Ariana Grande\', \'spotify:track:0KjAxsrYSvN0xGuh3cKPxD\', or \'https://open.spotify.com/playlist/37i9dQZF1DXcxvFzl58uP7\'"
 	)]
-	pub input: String,
+	pub input: Option<String>,
+
+	/// Format of the download progress, human in a terminal and plain otherwise
+	#[arg(long, value_enum)]
+	pub output: Option<OutputFormat>,
+
+	/// Only print errors and the final summary
+	#[arg(short, long)]
+	pub quiet: bool,
+
+	/// Where to write the report of the run, last_run.json next to the settings by default
+	#[arg(long, value_name = "PATH")]
+	pub report: Option<PathBuf>,
+
+	/// Download the failed tracks of a previous report again
+	#[arg(long, value_name = "REPORT", conflicts_with = "input")]
+	pub retry_failed: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+	/// Dashboard redrawn in the terminal
+	Human,
+	/// One line with time, state and title per change
+	Plain,
+	/// One JSON object per line on stdout, logs on stderr
+	Json,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Commands {
+	/// Rewrite the tags of already downloaded files with fresh metadata
+	Retag {
+		/// Directory to scan for downloaded files
+		path: PathBuf,
+		/// Only show the changes without writing them
+		#[arg(long)]
+		dry_run: bool,
+	},
+	/// Move already downloaded files into the layout of the current path templates
+	Reorganize {
+		/// Directory to scan for downloaded files
+		#[arg(required_unless_present = "undo")]
+		path: Option<PathBuf>,
+		/// Only show the planned moves without moving anything
+		#[arg(long)]
+		dry_run: bool,
+		/// Revert a previous reorganization using its undo log
+		#[arg(long, conflicts_with_all = ["path", "dry_run"])]
+		undo: Option<PathBuf>,
+	},
 }
 
 impl Args {
@@ -24,6 +82,6 @@ fn get_command() -> Command {
 			"Settings file located at: {}",
 			settings::get_config_settings_path().to_string_lossy()
 		));
-	
+
 	Args::augment_args(cli)
 }
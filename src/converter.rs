@@ -1,16 +1,167 @@
 use lewton::inside_ogg::OggStreamReader;
+use serde::{Deserialize, Serialize};
 use std::io::{Error, ErrorKind, Read, Seek};
 
 use crate::downloader::{AudioFormat, Quality};
 use crate::error::SpotifyError;
-use crate::error::SpotifyError::{InvalidFormat, LameConverterError};
+use crate::error::SpotifyError::{InvalidConfig, InvalidFormat};
+use crate::lame::Lame;
+use crate::normalisation::Normaliser;
+
+/// Bitrates allowed by MPEG-1 Layer III
+const MPEG1_BITRATES: [u32; 14] = [
+	32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+];
+/// Bitrates allowed by MPEG-2 and MPEG-2.5 Layer III
+const MPEG2_BITRATES: [u32; 14] = [8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+/// Sample rates of MPEG-1, MPEG-2 and MPEG-2.5
+const MPEG1_SAMPLE_RATES: [u32; 3] = [32000, 44100, 48000];
+const MPEG2_SAMPLE_RATES: [u32; 6] = [8000, 11025, 12000, 16000, 22050, 24000];
+
+/// Bitrate mode of the MP3 encoder
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum BitrateMode {
+	/// Constant bitrate
+	#[default]
+	Cbr,
+	/// Average bitrate
+	Abr,
+	/// Variable bitrate using `vbr_quality`
+	Vbr,
+}
+
+/// MP3 encoder settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mp3Config {
+	pub mode: BitrateMode,
+	/// Bitrate in kbps for CBR and ABR, defaults to the bitrate of the source,
+	/// at most 160 when resampling to an MPEG-2 sample rate
+	pub bitrate: Option<u32>,
+	/// VBR quality, 0 (V0, best) to 9 (V9)
+	pub vbr_quality: u8,
+	/// Encoder algorithm quality, 0 (best, slowest) to 9 (worst, fastest)
+	pub encoder_quality: u8,
+	pub joint_stereo: bool,
+	/// Output sample rate in Hz, keeps the source sample rate if unset
+	pub resample: Option<u32>,
+}
+
+impl Default for Mp3Config {
+	fn default() -> Self {
+		Mp3Config {
+			mode: BitrateMode::Cbr,
+			bitrate: None,
+			vbr_quality: 2,
+			encoder_quality: 0,
+			joint_stereo: false,
+			resample: None,
+		}
+	}
+}
+
+impl Mp3Config {
+	/// Check for invalid values and combinations
+	pub fn validate(&self) -> Result<(), SpotifyError> {
+		if self.vbr_quality > 9 {
+			return Err(InvalidConfig(format!(
+				"mp3.vbr_quality must be 0-9, got {}",
+				self.vbr_quality
+			)));
+		}
+		if self.encoder_quality > 9 {
+			return Err(InvalidConfig(format!(
+				"mp3.encoder_quality must be 0-9, got {}",
+				self.encoder_quality
+			)));
+		}
+		if self.mode == BitrateMode::Vbr && self.bitrate.is_some() {
+			return Err(InvalidConfig(
+				"mp3.bitrate can't be used with VBR, use mp3.vbr_quality".to_string(),
+			));
+		}
+		if let Some(rate) = self.resample {
+			if !MPEG1_SAMPLE_RATES.contains(&rate) && !MPEG2_SAMPLE_RATES.contains(&rate) {
+				return Err(InvalidConfig(format!(
+					"mp3.resample: {} Hz isn't a valid MP3 sample rate",
+					rate
+				)));
+			}
+		}
+		if let Some(bitrate) = self.bitrate {
+			let allowed = match self.resample {
+				Some(rate) if MPEG2_SAMPLE_RATES.contains(&rate) => MPEG2_BITRATES.to_vec(),
+				Some(_) => MPEG1_BITRATES.to_vec(),
+				// Source sample rate is unknown until decoding
+				None => {
+					let mut all = [MPEG1_BITRATES, MPEG2_BITRATES].concat();
+					all.sort_unstable();
+					all.dedup();
+					all
+				}
+			};
+			if !allowed.contains(&bitrate) {
+				return Err(InvalidConfig(format!(
+					"mp3.bitrate: {} kbps isn't valid{}",
+					bitrate,
+					match self.resample {
+						Some(rate) => format!(" at {} Hz", rate),
+						None => String::new(),
+					}
+				)));
+			}
+		}
+		Ok(())
+	}
+
+	/// Create configured encoder
+	fn encoder(
+		&self,
+		quality: Quality,
+		channels: u8,
+		sample_rate: u32,
+	) -> Result<Lame, SpotifyError> {
+		let source = match quality {
+			Quality::Q320 => 320,
+			Quality::Q256 => 256,
+			Quality::Q160 => 160,
+			Quality::Q96 => 96,
+		};
+		let bitrate = match (self.bitrate, self.resample) {
+			(Some(bitrate), _) => bitrate,
+			// MPEG-2 doesn't allow more than 160 kbps
+			(None, Some(rate)) if MPEG2_SAMPLE_RATES.contains(&rate) => source.min(160),
+			(None, _) => source,
+		};
+
+		let mut lame = Lame::new()?;
+		lame.set_channels(channels)?;
+		lame.set_sample_rate(sample_rate)?;
+		if let Some(rate) = self.resample {
+			lame.set_out_sample_rate(rate)?;
+		}
+		lame.set_quality(self.encoder_quality)?;
+		lame.set_write_vbr_tag(true)?;
+		if channels == 2 {
+			lame.set_joint_stereo(self.joint_stereo)?;
+		}
+		match self.mode {
+			BitrateMode::Cbr => lame.set_cbr(bitrate)?,
+			BitrateMode::Abr => lame.set_abr(bitrate)?,
+			BitrateMode::Vbr => lame.set_vbr(self.vbr_quality)?,
+		}
+		lame.init_params()?;
+		Ok(lame)
+	}
+}
 
 /// Converts audio to MP3
 pub enum AudioConverter {
 	Ogg {
 		decoder: OggStreamReader<ReadWrap>,
-		lame: lame::Lame,
+		lame: Lame,
 		lame_end: bool,
+		normaliser: Option<Normaliser>,
 	},
 }
 
@@ -22,57 +173,42 @@ impl AudioConverter {
 		read: Box<(dyn Read + Send + 'static)>,
 		format: AudioFormat,
 		quality: Quality,
+		config: &Mp3Config,
+		gain_db: Option<f32>,
+		limiter: bool,
 	) -> Result<AudioConverter, SpotifyError> {
-		// Create encoder
-		let bitrate = match quality {
-			Quality::Q320 => 320,
-			Quality::Q256 => 256,
-			Quality::Q160 => 160,
-			Quality::Q96 => 96,
-		};
-
-		let mut lame = lame::Lame::new().unwrap();
-
-		match lame.set_channels(2) {
-			Ok(_) => {}
-			Err(_) => return Err(LameConverterError("Channels".to_string())),
-		};
-
-		match lame.set_quality(0) {
-			Ok(_) => {}
-			Err(_) => return Err(LameConverterError("Quality".to_string())),
-		};
-		match lame.set_kilobitrate(bitrate) {
-			Ok(_) => {}
-			Err(_) => return Err(LameConverterError("Bitrate".to_string())),
-		};
-
 		match format {
-			AudioFormat::Aac => todo!(),
 			// Lewton decoder
 			AudioFormat::Ogg => {
 				let decoder = OggStreamReader::new(ReadWrap::new(Box::new(read)))?;
-				let sample_rate = decoder.ident_hdr.audio_sample_rate;
-				// Init lame
-				match lame.set_sample_rate(sample_rate) {
-					Ok(_) => {}
-					Err(_) => return Err(LameConverterError("Sample rate".to_string())),
-				};
-				match lame.init_params() {
-					Ok(_) => {}
-					Err(_) => return Err(LameConverterError("Init".to_string())),
-				};
+				let lame = config.encoder(
+					quality,
+					decoder.ident_hdr.audio_channels,
+					decoder.ident_hdr.audio_sample_rate,
+				)?;
+
+				let normaliser = gain_db.map(|gain_db| {
+					Normaliser::new(gain_db, limiter, decoder.ident_hdr.audio_sample_rate)
+				});
 
 				Ok(AudioConverter::Ogg {
 					lame,
 					decoder,
 					lame_end: false,
+					normaliser,
 				})
 			}
-			AudioFormat::Mp3 => panic!("No reencoding allowd!"),
+			// MP3 isn't reencoded, AAC can't be decoded
 			_ => Err(InvalidFormat),
 		}
 	}
+
+	/// Xing/LAME header which replaces the first frame once the stream is fully read
+	pub fn lametag_frame(&self) -> Vec<u8> {
+		match self {
+			AudioConverter::Ogg { lame, .. } => lame.lametag_frame(),
+		}
+	}
 }
 
 impl Read for AudioConverter {
@@ -82,46 +218,36 @@ impl Read for AudioConverter {
 				decoder,
 				lame,
 				lame_end,
+				normaliser,
 			} => {
-				match decoder.read_dec_packet() {
-					Ok(packet) => match packet {
-						Some(data) => {
-							// 0 sized packets aren't EOF
-							if data[0].is_empty() {
-								return self.read(buf);
-							}
-
-							let result = match lame.encode(&data[0], &data[1], buf) {
-								Ok(size) => {
-									if size == 0 {
-										return self.read(buf);
-									}
-									size
-								}
-								Err(e) => {
-									return Err(Error::new(
-										ErrorKind::InvalidData,
-										format!("Lame error: {:?}", e),
-									));
-								}
-							};
-							Ok(result as usize)
+				let packet = decoder
+					.read_dec_packet()
+					.map_err(|e| to_io_error(e.into()))?;
+				match packet {
+					Some(mut data) => {
+						// 0 sized packets aren't EOF
+						if data[0].is_empty() {
+							return self.read(buf);
 						}
-						None => {
-							if *lame_end {
-								return Ok(0);
-							}
-							*lame_end = true;
-							Ok(0)
+						if let Some(normaliser) = normaliser {
+							normaliser.process(&mut data);
 						}
-					},
-					Err(e) => {
-						// Close lame
-						if !*lame_end {
-							*lame_end = true;
+
+						// Right channel is ignored for mono
+						let right = data.get(1).unwrap_or(&data[0]);
+						let size = lame.encode(&data[0], right, buf).map_err(to_io_error)?;
+						if size == 0 {
+							return self.read(buf);
 						}
-						warn!("Lawton error: {}, calling EOF", e);
-						Ok(0)
+						Ok(size)
+					}
+					None => {
+						if *lame_end {
+							return Ok(0);
+						}
+						*lame_end = true;
+						// Last frames are still buffered in the encoder
+						lame.flush(buf).map_err(to_io_error)
 					}
 				}
 			}
@@ -129,6 +255,11 @@ impl Read for AudioConverter {
 	}
 }
 
+/// Pass error through `Read`, converted back by `From<std::io::Error>`
+fn to_io_error(e: SpotifyError) -> Error {
+	Error::new(ErrorKind::InvalidData, e)
+}
+
 pub struct ReadWrap {
 	source: Box<(dyn Read + Send + 'static)>,
 }
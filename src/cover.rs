@@ -0,0 +1,176 @@
+use async_std::sync::Mutex;
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+use crate::error::SpotifyError;
+use crate::ratelimit::RateLimiter;
+
+/// Amount of albums to keep covers of in memory
+const CACHE_SIZE: usize = 16;
+
+/// Mime and data of cover
+pub type Cover = (String, Vec<u8>);
+
+/// Which of the available cover sizes to use
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CoverSize {
+	Smallest,
+	Largest,
+	/// Largest cover which fits within the dimension, smallest if none fits
+	MaxDimension(u32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoverConfig {
+	/// Embed cover into tags
+	pub embed: bool,
+	pub size: CoverSize,
+	/// Re-encode cover to JPEG, resizing it to fit within the dimension if set
+	pub reencode: bool,
+	pub max_dimension: Option<u32>,
+	pub jpeg_quality: u8,
+	/// Filename of the cover written once per album directory, eg. `cover.jpg` or `folder.jpg`
+	pub sidecar: Option<String>,
+	/// Fail the download if the cover couldn't be fetched
+	pub required: bool,
+}
+
+impl Default for CoverConfig {
+	fn default() -> Self {
+		CoverConfig {
+			embed: true,
+			size: CoverSize::Largest,
+			reencode: false,
+			max_dimension: None,
+			jpeg_quality: 90,
+			sidecar: None,
+			required: false,
+		}
+	}
+}
+
+impl CoverConfig {
+	/// Whether the cover is embedded or written anywhere
+	pub fn is_used(&self) -> bool {
+		self.embed || self.sidecar.is_some()
+	}
+
+	/// Select image according to size
+	pub fn select<'a>(&self, images: &'a [aspotify::Image]) -> Option<&'a aspotify::Image> {
+		let dimension = |i: &aspotify::Image| {
+			i.width
+				.map(|w| w as u32)
+				.max(i.height.map(|h| h as u32))
+				.unwrap_or(0)
+		};
+		match self.size {
+			CoverSize::Smallest => images.iter().min_by_key(|i| dimension(i)),
+			CoverSize::Largest => images.iter().max_by_key(|i| dimension(i)),
+			CoverSize::MaxDimension(max) => images
+				.iter()
+				.filter(|i| dimension(i) <= max)
+				.max_by_key(|i| dimension(i))
+				.or_else(|| images.iter().min_by_key(|i| dimension(i))),
+		}
+	}
+
+	/// Re-encode and resize cover if enabled ( BLOCKING )
+	pub fn process(&self, cover: Cover) -> Result<Cover, SpotifyError> {
+		if !self.reencode {
+			return Ok(cover);
+		}
+
+		let mut image = image::load_from_memory(&cover.1)
+			.map_err(|e| SpotifyError::Error(format!("Invalid cover: {}", e)))?;
+		if let Some(max) = self.max_dimension {
+			let (width, height) = image.dimensions();
+			if width > max || height > max {
+				image = image.resize(max, max, FilterType::Lanczos3);
+			}
+		}
+
+		let mut data = vec![];
+		DynamicImage::ImageRgb8(image.to_rgb8())
+			.write_with_encoder(JpegEncoder::new_with_quality(&mut data, self.jpeg_quality))
+			.map_err(|e| SpotifyError::Error(format!("Failed encoding cover: {}", e)))?;
+		Ok(("image/jpeg".to_string(), data))
+	}
+
+	/// Write sidecar cover into directory, unless it already exists
+	pub async fn write_sidecar(
+		&self,
+		directory: impl AsRef<Path>,
+		cover: &Cover,
+	) -> Result<(), SpotifyError> {
+		if let Some(filename) = &self.sidecar {
+			let path = directory.as_ref().join(filename);
+			if !path.exists() {
+				tokio::fs::write(path, &cover.1).await?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Processed covers by album ID, so every album is fetched only once
+///
+/// Failed fetches aren't cached, the next track of the album tries again.
+#[derive(Debug, Clone, Default)]
+pub struct CoverCache {
+	covers: Arc<Mutex<VecDeque<(String, Arc<OnceCell<Cover>>)>>>,
+	limiter: RateLimiter,
+}
+
+impl CoverCache {
+	/// Get cover of album, fetching and processing it if it isn't cached yet
+	pub async fn get(
+		&self,
+		album_id: &str,
+		url: &str,
+		config: &CoverConfig,
+	) -> Result<Cover, SpotifyError> {
+		let cell = {
+			let mut covers = self.covers.lock().await;
+			match covers.iter().find(|(id, _)| id == album_id) {
+				Some((_, cell)) => cell.clone(),
+				None => {
+					let cell = Arc::new(OnceCell::new());
+					covers.push_back((album_id.to_string(), cell.clone()));
+					if covers.len() > CACHE_SIZE {
+						covers.pop_front();
+					}
+					cell
+				}
+			}
+		};
+
+		cell.get_or_try_init(|| async {
+			let cover = download_cover(&self.limiter, url).await?;
+			let config = config.clone();
+			tokio::task::spawn_blocking(move || config.process(cover)).await?
+		})
+		.await
+		.cloned()
+	}
+}
+
+/// Download cover, returns mime and data
+async fn download_cover(limiter: &RateLimiter, url: &str) -> Result<Cover, SpotifyError> {
+	let res = limiter.send(reqwest::Client::new().get(url)).await?;
+	let mime = res
+		.headers()
+		.get("content-type")
+		.ok_or_else(|| SpotifyError::Error("Missing cover mime!".into()))?
+		.to_str()
+		.map_err(|e| SpotifyError::Error(format!("Invalid cover mime: {}", e)))?
+		.to_string();
+	let data = res.bytes().await?.to_vec();
+	Ok((mime, data))
+}
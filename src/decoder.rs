@@ -0,0 +1,80 @@
+use lewton::inside_ogg::OggStreamReader;
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::downloader::AudioFormat;
+use crate::error::SpotifyError;
+
+/// Decode whole file, calling `f` with the planar samples and sample rate of every packet ( BLOCKING )
+pub fn decode_file(
+	path: impl AsRef<Path>,
+	format: AudioFormat,
+	f: impl FnMut(&[Vec<i16>], u32),
+) -> Result<(), SpotifyError> {
+	decode_file_limit(path, format, None, f)
+}
+
+/// Decode file up to `limit`, or whole file if `None` ( BLOCKING )
+pub fn decode_file_limit(
+	path: impl AsRef<Path>,
+	format: AudioFormat,
+	limit: Option<Duration>,
+	mut f: impl FnMut(&[Vec<i16>], u32),
+) -> Result<(), SpotifyError> {
+	let limit = limit.map(|l| l.as_secs_f64()).unwrap_or(f64::INFINITY);
+	let mut decoded = 0.0;
+	let mut f = |packet: &[Vec<i16>], sample_rate: u32| {
+		decoded += packet.first().map(|c| c.len()).unwrap_or(0) as f64 / sample_rate as f64;
+		f(packet, sample_rate);
+		decoded < limit
+	};
+	let file = File::open(path)?;
+	match format {
+		AudioFormat::Ogg => {
+			let mut decoder = OggStreamReader::new(file)?;
+			let sample_rate = decoder.ident_hdr.audio_sample_rate;
+			while let Some(packet) = decoder.read_dec_packet()? {
+				if !f(&packet, sample_rate) {
+					break;
+				}
+			}
+			Ok(())
+		}
+		AudioFormat::Mp3 => {
+			let mut decoder = minimp3::Decoder::new(file);
+			loop {
+				match decoder.next_frame() {
+					Ok(frame) => {
+						let channels = frame.channels.max(1);
+						let mut packet = vec![vec![]; channels];
+						for (i, sample) in frame.data.into_iter().enumerate() {
+							packet[i % channels].push(sample);
+						}
+						if !f(&packet, frame.sample_rate as u32) {
+							return Ok(());
+						}
+					}
+					Err(minimp3::Error::Eof) => return Ok(()),
+					// ID3 tags and garbage between frames
+					Err(minimp3::Error::SkippedData) | Err(minimp3::Error::InsufficientData) => {}
+					Err(minimp3::Error::Io(e)) => return Err(e.into()),
+				}
+			}
+		}
+		_ => Err(SpotifyError::InvalidFormat),
+	}
+}
+
+/// Decode whole file and get its duration ( BLOCKING )
+pub fn decoded_duration(
+	path: impl AsRef<Path>,
+	format: AudioFormat,
+) -> Result<Duration, SpotifyError> {
+	let mut seconds = 0.0;
+	decode_file(path, format, |packet, sample_rate| {
+		let samples = packet.first().map(|c| c.len()).unwrap_or(0);
+		seconds += samples as f64 / sample_rate as f64;
+	})?;
+	Ok(Duration::from_secs_f64(seconds))
+}
@@ -1,53 +1,107 @@
 use aspotify::Tracks;
 use async_std::channel::{bounded, Receiver, Sender};
-use async_stream::try_stream;
+use async_std::sync::Mutex;
+use async_stream::{stream, try_stream};
 use chrono::NaiveDate;
-use futures::stream::FuturesUnordered;
+use futures::stream::{BoxStream, FuturesUnordered};
 use futures::{pin_mut, select, FutureExt, Stream, StreamExt};
-use librespot::audio::{AudioDecrypt, AudioFile};
-use librespot::core::audio_key::AudioKey;
-use librespot::core::session::Session;
-use librespot::core::spotify_id::SpotifyId;
-use librespot::metadata::{Metadata, Track};
 use librespot::protocol::metadata::audio_file::Format as FileFormat;
 use sanitize_filename::sanitize;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
-use std::io::Read;
+use std::io::{Read, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use tokio::fs::File;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::broadcast::{self, error::RecvError};
 
-use crate::converter::AudioConverter;
+use crate::converter::{AudioConverter, Mp3Config};
+use crate::cover::{Cover, CoverCache, CoverConfig};
+use crate::decoder;
 use crate::error::SpotifyError;
+use crate::metadata::MetadataProvider;
+use crate::normalisation::{NormalisationConfig, NormalisationData, NormalisationMode};
+use crate::reader::BlockingReader;
+use crate::replaygain::{self, TrackLoudness};
+use crate::source::{AudioSource, LibrespotSource};
 use crate::spotify::{Spotify, SpotifyItem};
+use crate::stats::{DownloadStats, StatsTracker};
 use crate::tag::{Field, TagWrap};
+use crate::verify::{self, VerifyMode};
+
+/// Events buffered for every subscriber
+const EVENT_BUFFER: usize = 1024;
 
 /// Wrapper for use with UI
+///
+/// Queues tracks and downloads them in the background, `get_downloads` returns their state.
 #[derive(Debug, Clone)]
 pub struct Downloader {
 	rx: Receiver<Response>,
 	tx: Sender<Message>,
+	events: broadcast::Sender<DownloadEvent>,
 
-	spotify: Spotify,
+	metadata: Arc<dyn MetadataProvider>,
 }
 impl Downloader {
 	/// Create new instance
 	pub fn new(config: DownloaderConfig, spotify: Spotify) -> Downloader {
+		let audio = LibrespotSource::new(spotify.session.clone());
+		Downloader::with_sources(config, Arc::new(spotify), Arc::new(audio))
+	}
+
+	/// Create new instance with custom metadata and audio sources
+	pub fn with_sources(
+		config: DownloaderConfig,
+		metadata: Arc<dyn MetadataProvider>,
+		audio: Arc<dyn AudioSource>,
+	) -> Downloader {
 		let (tx_0, rx_0) = bounded(1);
 		let (tx_1, rx_1) = bounded(1);
 
+		let (events, _) = broadcast::channel(EVENT_BUFFER);
+
 		let tx_clone = tx_1.clone();
-		let spotify_clone = spotify.clone();
+		let events_clone = events.clone();
+		let metadata_clone = metadata.clone();
 		tokio::spawn(async move {
-			communication_thread(config, spotify_clone, rx_1, tx_0, tx_clone).await
+			communication_thread(
+				config,
+				metadata_clone,
+				audio,
+				rx_1,
+				tx_0,
+				tx_clone,
+				events_clone,
+			)
+			.await
 		});
 		Downloader {
 			rx: rx_0,
 			tx: tx_1,
-			spotify,
+			events,
+			metadata,
 		}
 	}
+
+	/// Subscribe to events of all downloads
+	///
+	/// Subscribers which fall behind get `DownloadEvent::Lagged`, `get_downloads` has the current state.
+	pub fn subscribe(&self) -> BoxStream<'static, DownloadEvent> {
+		let mut rx = self.events.subscribe();
+		Box::pin(stream! {
+			loop {
+				match rx.recv().await {
+					Ok(event) => yield event,
+					Err(RecvError::Lagged(skipped)) => yield DownloadEvent::Lagged(skipped),
+					Err(RecvError::Closed) => break,
+				}
+			}
+		})
+	}
 	/// Add item to download queue
 	pub async fn add_to_queue(&self, download: Download) {
 		self.tx
@@ -58,6 +112,11 @@ impl Downloader {
 
 	/// Add multiple items to queue
 	pub async fn add_to_queue_multiple(&self, downloads: Vec<Download>) {
+		// Batched metadata instead of single requests in every job
+		let track_ids: Vec<String> = downloads.iter().map(|d| d.track_id.clone()).collect();
+		if let Err(e) = self.metadata.prefetch(&track_ids).await {
+			warn!("Failed prefetching metadata! {}", e);
+		}
 		self.tx.send(Message::AddToQueue(downloads)).await.unwrap();
 	}
 
@@ -71,7 +130,7 @@ impl Downloader {
 			Ok(None)
 		} else {
 			let results: Vec<SearchResult> = self
-				.spotify
+				.metadata
 				.search(input)
 				.await?
 				.into_iter()
@@ -84,31 +143,47 @@ impl Downloader {
 
 	/// Add URL or URI to queue
 	pub async fn add_uri(&self, uri: &str) -> Result<(), SpotifyError> {
+		self.add_uri_from(uri, None).await
+	}
+
+	/// Add URL or URI to queue, recording `source` instead of the URI as origin of its tracks
+	pub async fn add_uri_from(&self, uri: &str, source: Option<&str>) -> Result<(), SpotifyError> {
 		let uri = Spotify::parse_uri(uri)?;
-		let item = self.spotify.resolve_uri(&uri).await?;
+		let source = source.unwrap_or(&uri).to_string();
+		let with_source = |download: Download| Download {
+			source: Some(source.clone()),
+			..download
+		};
+		let item = self.metadata.resolve_uri(&uri).await?;
 		match item {
 			SpotifyItem::Track(t) => {
 				if !t.is_local {
-					self.add_to_queue(t.into()).await;
+					self.add_to_queue(with_source(t.into())).await;
 				}
 			}
 			SpotifyItem::Album(a) => {
-				let tracks = self.spotify.full_album(&a.id).await?;
-				let queue: Vec<Download> = tracks.into_iter().map(|t| t.into()).collect();
-				self.add_to_queue_multiple(queue).await;
-			}
-			SpotifyItem::Playlist(p) => {
-				let tracks = self.spotify.full_playlist(&p.id).await?;
+				let tracks = self.metadata.full_album(&a.id).await?;
 				let queue: Vec<Download> = tracks
 					.into_iter()
-					.filter(|t| !t.is_local)
-					.map(|t| t.into())
+					.map(|t| with_source(Download::from_album_track(t, &a.id)))
 					.collect();
 				self.add_to_queue_multiple(queue).await;
 			}
+			SpotifyItem::Playlist(p) => {
+				let tracks = self.metadata.full_playlist(&p.id).await?;
+				let mut queue: Vec<Download> = vec![];
+				for track in tracks.into_iter().filter(|t| !t.is_local) {
+					self.metadata.cache_track(track.clone()).await;
+					queue.push(with_source(track.into()));
+				}
+				self.add_to_queue_multiple(queue).await;
+			}
 			SpotifyItem::Artist(a) => {
-				let tracks = self.spotify.full_artist(&a.id).await?;
-				let queue: Vec<Download> = tracks.into_iter().map(|t| t.into()).collect();
+				let tracks = self.metadata.full_artist(&a.id).await?;
+				let queue: Vec<Download> = tracks
+					.into_iter()
+					.map(|(album_id, t)| with_source(Download::from_album_track(t, &album_id)))
+					.collect();
 				self.add_to_queue_multiple(queue).await;
 			}
 
@@ -121,54 +196,84 @@ impl Downloader {
 		Ok(())
 	}
 
+	/// Cancel download, stopping it if it's already running
+	pub async fn cancel(&self, id: i64) {
+		self.tx.send(Message::Cancel(id)).await.unwrap();
+	}
+
+	/// Queue failed or cancelled download again
+	pub async fn retry(&self, id: i64) {
+		self.tx.send(Message::Retry(id)).await.unwrap();
+	}
+
+	/// Download next, before the rest of the queue
+	pub async fn prioritize(&self, id: i64) {
+		self.tx.send(Message::Prioritize(id)).await.unwrap();
+	}
+
 	/// Get all downloads
 	pub async fn get_downloads(&self) -> Vec<Download> {
 		self.tx.send(Message::GetDownloads).await.unwrap();
 		let Response::Downloads(d) = self.rx.recv().await.unwrap();
 		d
 	}
+
+	/// Get throughput, remaining time and time spent per stage
+	pub async fn get_stats(&self) -> DownloadStats {
+		let (tx, rx) = bounded(1);
+		self.tx.send(Message::GetStats(tx)).await.unwrap();
+		rx.recv().await.unwrap()
+	}
 }
 
 async fn communication_thread(
 	config: DownloaderConfig,
-	spotify: Spotify,
+	metadata: Arc<dyn MetadataProvider>,
+	audio: Arc<dyn AudioSource>,
 	rx: Receiver<Message>,
 	tx: Sender<Response>,
 	self_tx: Sender<Message>,
+	events: broadcast::Sender<DownloadEvent>,
 ) {
 	// Downloader
-	let downloader = DownloaderInternal::new(spotify.clone(), self_tx.clone());
+	let downloader = DownloaderInternal::new(metadata, audio, self_tx.clone());
 	let downloader_tx = downloader.tx.clone();
+	let cancellations = downloader.cancellations.clone();
+	let albums = downloader.albums.clone();
 	tokio::spawn(async move {
 		downloader.download_loop().await;
 	});
 	let mut waiting_for_job = false;
 	let mut queue: Vec<Download> = vec![];
+	let mut stats = StatsTracker::default();
 
 	// Receive messages
 	while let Ok(msg) = rx.recv().await {
 		match msg {
 			// Send job to worker thread
-			Message::GetJob => {
-				if let Some(d) = queue.iter_mut().find(|i| i.state == DownloadState::None) {
-					d.state = DownloadState::Lock;
+			Message::GetJob => match next_job(&mut queue, &events, &mut stats) {
+				Some(job) => {
 					downloader_tx
-						.send(DownloaderMessage::Job(d.clone().into(), config.clone()))
+						.send(DownloaderMessage::Job(job, config.clone()))
 						.await
 						.unwrap();
 					waiting_for_job = false;
-				} else {
-					waiting_for_job = true;
 				}
-			}
+				None => waiting_for_job = true,
+			},
 			// Update state of download
 			Message::UpdateState(id, state) => {
 				let i = queue.iter().position(|i| i.id == id).unwrap();
-				queue[i].state = state.clone();
+				update_state(&mut queue[i], state, &events, &mut stats);
+			}
+			// Path of the downloaded file
+			Message::UpdatePath(id, path) => {
+				let i = queue.iter().position(|i| i.id == id).unwrap();
+				queue[i].path = Some(path);
 			}
 			Message::AddToQueue(download) => {
 				// Assign new IDs and reset state
-				let mut id = queue.iter().map(|i| i.id).max().unwrap_or(0);
+				let mut id = queue.iter().map(|i| i.id + 1).max().unwrap_or(0);
 				let downloads: Vec<Download> = download
 					.into_iter()
 					.map(|mut d| {
@@ -178,32 +283,169 @@ async fn communication_thread(
 						d
 					})
 					.collect();
+				for download in &downloads {
+					stats.queued(download.id);
+					events.send(DownloadEvent::Queued(download.clone())).ok();
+				}
 				queue.extend(downloads);
 				// Update worker threads if locked
 				if waiting_for_job {
-					let d = queue
-						.iter_mut()
-						.find(|i| i.state == DownloadState::None)
-						.unwrap();
-					d.state = DownloadState::Lock;
-					downloader_tx
-						.send(DownloaderMessage::Job(d.clone().into(), config.clone()))
-						.await
-						.unwrap();
-					waiting_for_job = false;
+					if let Some(job) = next_job(&mut queue, &events, &mut stats) {
+						downloader_tx
+							.send(DownloaderMessage::Job(job, config.clone()))
+							.await
+							.unwrap();
+						waiting_for_job = false;
+					}
+				}
+			}
+			Message::Cancel(id) => {
+				if let Some(i) = queue.iter().position(|d| d.id == id) {
+					match queue[i].state {
+						// Not sent to a worker yet
+						DownloadState::None => {
+							update_state(
+								&mut queue[i],
+								DownloadState::Error(SpotifyError::Cancelled),
+								&events,
+								&mut stats,
+							);
+							// Counts as finished track of its album
+							if config.replaygain {
+								let job = create_job(&queue, &queue[i]);
+								let (albums, config) = (albums.clone(), config.clone());
+								tokio::spawn(async move {
+									if let Err(e) = albums.finish(&job, None, &config).await {
+										warn!("Failed writing album ReplayGain! {}", e);
+									}
+								});
+							}
+						}
+						// The worker stops it and reports the error
+						DownloadState::Lock
+						| DownloadState::Downloading(_, _)
+						| DownloadState::Post => cancellations.cancel(id),
+						DownloadState::Done | DownloadState::Error(_) => {}
+					}
+				}
+			}
+			Message::Retry(id) => {
+				let download = match queue.iter_mut().find(|d| d.id == id) {
+					Some(download) if matches!(download.state, DownloadState::Error(_)) => download,
+					_ => continue,
+				};
+				cancellations.clear(id);
+				download.path = None;
+				update_state(download, DownloadState::None, &events, &mut stats);
+				if waiting_for_job {
+					if let Some(job) = next_job(&mut queue, &events, &mut stats) {
+						downloader_tx
+							.send(DownloaderMessage::Job(job, config.clone()))
+							.await
+							.unwrap();
+						waiting_for_job = false;
+					}
+				}
+			}
+			// Jobs are taken from the front of the queue
+			Message::Prioritize(id) => {
+				if let Some(i) = queue
+					.iter()
+					.position(|d| d.id == id && d.state == DownloadState::None)
+				{
+					let download = queue.remove(i);
+					queue.insert(0, download);
 				}
 			}
 			Message::GetDownloads => {
 				tx.send(Response::Downloads(queue.clone())).await.ok();
 			}
+			Message::GetStats(tx) => {
+				tx.send(stats.stats()).await.ok();
+			}
 		}
 	}
 }
 
+/// Set state of download, record its statistics and notify subscribers
+fn update_state(
+	download: &mut Download,
+	state: DownloadState,
+	events: &broadcast::Sender<DownloadEvent>,
+	stats: &mut StatsTracker,
+) {
+	stats.update(download.id, &state);
+	let previous = std::mem::replace(&mut download.state, state.clone());
+	let id = download.id;
+	let event = match state {
+		DownloadState::Downloading(read, total) => {
+			if !matches!(previous, DownloadState::Downloading(..)) {
+				events.send(DownloadEvent::Started(id)).ok();
+			}
+			DownloadEvent::Progress(id, read, total)
+		}
+		DownloadState::Done => match &download.path {
+			Some(path) => DownloadEvent::Finished(id, path.clone()),
+			None => DownloadEvent::StateChanged(id, state),
+		},
+		DownloadState::Error(e) => DownloadEvent::Error(id, e),
+		state => DownloadEvent::StateChanged(id, state),
+	};
+	// No subscribers
+	events.send(event).ok();
+}
+
+/// Lock the first waiting download and create its job
+fn next_job(
+	queue: &mut [Download],
+	events: &broadcast::Sender<DownloadEvent>,
+	stats: &mut StatsTracker,
+) -> Option<DownloadJob> {
+	let i = queue.iter().position(|d| d.state == DownloadState::None)?;
+	update_state(&mut queue[i], DownloadState::Lock, events, stats);
+	Some(create_job(queue, &queue[i]))
+}
+
+/// IDs of running downloads which should stop, shared with the workers
+#[derive(Debug, Clone, Default)]
+struct Cancellations(Arc<std::sync::Mutex<HashSet<i64>>>);
+
+impl Cancellations {
+	fn cancel(&self, id: i64) {
+		self.0.lock().unwrap().insert(id);
+	}
+
+	fn clear(&self, id: i64) {
+		self.0.lock().unwrap().remove(&id);
+	}
+
+	fn is_cancelled(&self, id: i64) -> bool {
+		self.0.lock().unwrap().contains(&id)
+	}
+}
+
+/// Create job for download, counting the tracks of its album in the queue
+///
+/// Only tracks queued as a whole album are counted, others don't get album ReplayGain.
+fn create_job(queue: &[Download], download: &Download) -> DownloadJob {
+	let mut job: DownloadJob = download.clone().into();
+	if let (Some(album_id), true) = (&download.album_id, download.whole_album) {
+		job.album_tracks = queue
+			.iter()
+			.filter(|d| d.whole_album && d.album_id.as_ref() == Some(album_id))
+			.count();
+	}
+	job
+}
+
 /// Spotify downloader
 
 pub struct DownloaderInternal {
-	spotify: Spotify,
+	metadata: Arc<dyn MetadataProvider>,
+	audio: Arc<dyn AudioSource>,
+	covers: CoverCache,
+	albums: Albums,
+	cancellations: Cancellations,
 	pub tx: Sender<DownloaderMessage>,
 	rx: Receiver<DownloaderMessage>,
 	event_tx: Sender<Message>,
@@ -215,10 +457,18 @@ pub enum DownloaderMessage {
 
 impl DownloaderInternal {
 	/// Create new instance
-	pub fn new(spotify: Spotify, event_tx: Sender<Message>) -> DownloaderInternal {
+	pub fn new(
+		metadata: Arc<dyn MetadataProvider>,
+		audio: Arc<dyn AudioSource>,
+		event_tx: Sender<Message>,
+	) -> DownloaderInternal {
 		let (tx, rx) = bounded(1);
 		DownloaderInternal {
-			spotify,
+			metadata,
+			audio,
+			covers: CoverCache::default(),
+			albums: Albums::default(),
+			cancellations: Cancellations::default(),
 			tx,
 			rx,
 			event_tx,
@@ -265,40 +515,205 @@ impl DownloaderInternal {
 	/// Wrapper for download_job for error handling
 	async fn download_job_wrapper(&self, job: DownloadJob, config: DownloaderConfig) {
 		let id = job.id;
-		match self.download_job(job, config).await {
-			Ok(_) => {}
-			Err(e) => {
-				self.event_tx
-					.send(Message::UpdateState(id, DownloadState::Error(e)))
-					.await
-					.unwrap();
+		let mut attempt = 0;
+		let result = loop {
+			let result = self.download_job(job.clone(), config.clone()).await;
+			match &result {
+				Err(e) if e.is_retryable() && attempt < config.retries => {
+					attempt += 1;
+					warn!(
+						"{} failed: {}, retrying ({}/{})",
+						job.track_id, e, attempt, config.retries
+					);
+				}
+				_ => break result,
+			}
+		};
+
+		// Album ReplayGain, once every track of the album is finished
+		if config.replaygain {
+			let track = result.as_ref().ok().cloned().flatten();
+			if let Err(e) = self.albums.finish(&job, track, &config).await {
+				warn!("Failed writing album ReplayGain! {}", e);
 			}
 		}
+
+		self.cancellations.clear(id);
+		let state = match result {
+			Ok(_) => DownloadState::Done,
+			Err(e) => DownloadState::Error(e),
+		};
+		self.event_tx
+			.send(Message::UpdateState(id, state))
+			.await
+			.unwrap();
 	}
 
-	// Wrapper for downloading and tagging
+	// Wrapper for downloading and tagging, returns the analyzed track if ReplayGain is enabled
 	async fn download_job(
 		&self,
 		job: DownloadJob,
 		config: DownloaderConfig,
-	) -> Result<(), SpotifyError> {
+	) -> Result<Option<AnalyzedTrack>, SpotifyError> {
+		if self.cancellations.is_cancelled(job.id) {
+			return Err(SpotifyError::Cancelled);
+		}
 		// Fetch metadata
-		let track = self
-			.spotify
-			.spotify
-			.tracks()
-			.get_track(&job.track_id, None)
-			.await?
-			.data;
+		let track = self.metadata.get_track(&job.track_id).await?;
 		let album = self
-			.spotify
-			.spotify
-			.albums()
-			.get_album(&track.album.id.ok_or(SpotifyError::Unavailable)?, None)
-			.await?
-			.data;
-
-		let tags: Vec<(&str, String)> = vec![
+			.metadata
+			.get_album(track.album.id.as_ref().ok_or(SpotifyError::Unavailable)?)
+			.await?;
+
+		let path = DownloaderInternal::render_path(
+			&config,
+			&DownloaderInternal::template_values(&track, &job.track_id),
+		);
+
+		tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+
+		// Download
+		let (path, format, normalisation) = DownloaderInternal::download_track(
+			self.audio.as_ref(),
+			&job.track_id,
+			path,
+			config.clone(),
+			self.event_tx.clone(),
+			job.id,
+			&self.cancellations,
+		)
+		.await?;
+		self.event_tx
+			.send(Message::UpdatePath(job.id, path.clone()))
+			.await
+			.ok();
+		// Post processing
+		self.event_tx
+			.send(Message::UpdateState(job.id, DownloadState::Post))
+			.await
+			.ok();
+
+		// Truncated streams decode fine up to where they end, full verification checks it as well
+		if config.check_duration && config.verify != VerifyMode::Full {
+			let (path_clone, format_clone) = (path.clone(), format.clone());
+			let decoded = tokio::task::spawn_blocking(move || {
+				decoder::decoded_duration(path_clone, format_clone)
+			})
+			.await??;
+			if decoded + verify::DURATION_TOLERANCE < track.duration {
+				tokio::fs::remove_file(&path).await.ok();
+				return Err(SpotifyError::Truncated(
+					decoded.as_millis() as u64,
+					track.duration.as_millis() as u64,
+				));
+			}
+		}
+
+		// Verify before tags are written
+		if config.verify != VerifyMode::Off {
+			let (path_clone, format_clone) = (path.clone(), format.clone());
+			let (mode, expected) = (config.verify, track.duration);
+			let result = tokio::task::spawn_blocking(move || {
+				verify::verify_file(path_clone, format_clone, mode, expected)
+			})
+			.await?;
+			if let Err(e) = result {
+				tokio::fs::remove_file(&path).await.ok();
+				return Err(e);
+			}
+		}
+
+		// Download cover
+		let mut cover = None;
+		let image = match config.cover.is_used() {
+			true => config.cover.select(&track.album.images),
+			false => None,
+		};
+		if let Some(image) = image {
+			match self.covers.get(&album.id, &image.url, &config.cover).await {
+				Ok(c) => {
+					if let Some(directory) = path.parent() {
+						if let Err(e) = config.cover.write_sidecar(directory, &c).await {
+							warn!("Failed writing sidecar cover! {}", e);
+						}
+					}
+					if config.cover.embed {
+						cover = Some(c);
+					}
+				}
+				Err(e) if config.cover.required => return Err(e),
+				Err(e) => warn!("Failed downloading cover! {}", e),
+			}
+		}
+
+		let tags = DownloaderInternal::track_tags(&track, &album);
+		let date = album.release_date;
+		// Write tags
+		let (path_clone, format_clone, config_clone) =
+			(path.clone(), format.clone(), config.clone());
+		tokio::task::spawn_blocking(move || {
+			DownloaderInternal::write_tags(
+				path_clone,
+				job.track_id.to_string(),
+				format_clone,
+				tags,
+				date,
+				cover,
+				config_clone,
+			)
+		})
+		.await??;
+
+		// Spotify's loudness data, unless analyzing
+		if config.spotify_replaygain && !config.replaygain {
+			if let Some(normalisation) = normalisation {
+				let (path, format, config) = (path.clone(), format.clone(), config.clone());
+				tokio::task::spawn_blocking(move || {
+					DownloaderInternal::write_custom_tags(
+						path,
+						format,
+						normalisation.replaygain_tags(),
+						&config,
+					)
+				})
+				.await??;
+			}
+		}
+
+		// ReplayGain
+		let analyzed = match config.replaygain {
+			true => {
+				let (path_clone, format_clone) = (path.clone(), format.clone());
+				let loudness = tokio::task::spawn_blocking(move || {
+					let loudness = replaygain::analyze_file(&path_clone, format_clone.clone())?;
+					DownloaderInternal::write_custom_tags(
+						path_clone,
+						format_clone,
+						loudness.track_tags(),
+						&config,
+					)?;
+					Ok::<_, SpotifyError>(loudness)
+				})
+				.await??;
+				Some((path.clone(), format, loudness))
+			}
+			false => None,
+		};
+
+		// Cancelled while post processing, the file would be skipped by a retry otherwise
+		if self.cancellations.is_cancelled(job.id) {
+			tokio::fs::remove_file(&path).await.ok();
+			return Err(SpotifyError::Cancelled);
+		}
+		Ok(analyzed)
+	}
+
+	/// Get template variables of track
+	pub(crate) fn template_values(
+		track: &aspotify::Track,
+		track_id: &str,
+	) -> Vec<(&'static str, String)> {
+		vec![
 			("%title%", sanitize(&track.name)),
 			(
 				"%artist%",
@@ -327,7 +742,7 @@ impl DownloaderInternal {
 			("%0track%", format!("{:02}", track.track_number)),
 			("%disc%", track.disc_number.to_string()),
 			("%0disc%", format!("{:02}", track.disc_number)),
-			("%id%", job.track_id.to_string()),
+			("%id%", track_id.to_string()),
 			("%album%", sanitize(&track.album.name)),
 			(
 				"%albumArtist%",
@@ -354,44 +769,26 @@ impl DownloaderInternal {
 						.join(", "),
 				),
 			),
-		];
+		]
+	}
 
+	/// Fill template variables into path and filename template, without extension
+	pub(crate) fn render_path(config: &DownloaderConfig, values: &[(&str, String)]) -> PathBuf {
 		let mut filename_template = config.filename_template.clone();
 		let mut path_template = config.path.clone();
-		for (tag, value) in tags {
-			filename_template = filename_template.replace(tag, &value);
-			path_template = path_template.replace(tag, &value);
-		}
-		let path = Path::new(&path_template).join(&filename_template);
-
-		tokio::fs::create_dir_all(path.parent().unwrap()).await?;
-
-		// Download
-		let (path, format) = DownloaderInternal::download_track(
-			&self.spotify.session,
-			&job.track_id,
-			path,
-			config.clone(),
-			self.event_tx.clone(),
-			job.id,
-		)
-		.await?;
-		// Post processing
-		self.event_tx
-			.send(Message::UpdateState(job.id, DownloadState::Post))
-			.await
-			.ok();
-
-		// Download cover
-		let mut cover = None;
-		if let Some(image) = track.album.images.first() {
-			match DownloaderInternal::download_cover(&image.url).await {
-				Ok(c) => cover = Some(c),
-				Err(e) => warn!("Failed downloading cover! {}", e),
-			}
+		for (tag, value) in values {
+			filename_template = filename_template.replace(tag, value);
+			path_template = path_template.replace(tag, value);
 		}
+		Path::new(&path_template).join(&filename_template)
+	}
 
-		let tags = vec![
+	/// Get tag values of track
+	pub(crate) fn track_tags(
+		track: &aspotify::Track,
+		album: &aspotify::Album,
+	) -> Vec<(Field, Vec<String>)> {
+		vec![
 			(Field::Title, vec![track.name.to_string()]),
 			(Field::Album, vec![track.album.name.to_string()]),
 			(
@@ -415,43 +812,7 @@ impl DownloaderInternal {
 			(Field::DiscNumber, vec![track.disc_number.to_string()]),
 			(Field::Genre, album.genres.clone()),
 			(Field::Label, vec![album.label.to_string()]),
-		];
-		let date = album.release_date;
-		// Write tags
-		let config = config.clone();
-		tokio::task::spawn_blocking(move || {
-			DownloaderInternal::write_tags(
-				path,
-				job.track_id.to_string(),
-				format,
-				tags,
-				date,
-				cover,
-				config,
-			)
-		})
-		.await??;
-
-		// Done
-		self.event_tx
-			.send(Message::UpdateState(job.id, DownloadState::Done))
-			.await
-			.ok();
-		Ok(())
-	}
-
-	/// Download cover, returns mime and data
-	async fn download_cover(url: &str) -> Result<(String, Vec<u8>), SpotifyError> {
-		let res = reqwest::get(url).await?;
-		let mime = res
-			.headers()
-			.get("content-type")
-			.ok_or_else(|| SpotifyError::Error("Missing cover mime!".into()))?
-			.to_str()
-			.unwrap()
-			.to_string();
-		let data = res.bytes().await?.to_vec();
-		Ok((mime, data))
+		]
 	}
 
 	/// Write tags to file ( BLOCKING )
@@ -461,19 +822,62 @@ impl DownloaderInternal {
 		format: AudioFormat,
 		tags: Vec<(Field, Vec<String>)>,
 		date: NaiveDate,
-		cover: Option<(String, Vec<u8>)>,
+		cover: Option<Cover>,
 		config: DownloaderConfig,
 	) -> Result<(), SpotifyError> {
 		let mut tag_wrap = TagWrap::new(path, format)?;
-		// Format specific
+		DownloaderInternal::apply_tags(&mut tag_wrap, &track_id, tags, date, cover, &config);
+		tag_wrap.get_tag().save()?;
+		Ok(())
+	}
+
+	/// Write custom tags to file ( BLOCKING )
+	fn write_custom_tags(
+		path: impl AsRef<Path>,
+		format: AudioFormat,
+		tags: Vec<(&str, String)>,
+		config: &DownloaderConfig,
+	) -> Result<(), SpotifyError> {
+		let mut tag_wrap = TagWrap::new(path, format)?;
 		if let TagWrap::Id3(id3) = &mut tag_wrap {
 			id3.use_id3_v24(config.id3v24)
 		}
+		let tag = tag_wrap.get_tag();
+		tag.set_separator(&config.separator);
+		for (name, value) in tags {
+			tag.set_custom(name, vec![value]);
+		}
+		tag.save()
+	}
+
+	/// Set tags without saving
+	pub(crate) fn apply_tags(
+		tag_wrap: &mut TagWrap,
+		track_id: &str,
+		tags: Vec<(Field, Vec<String>)>,
+		date: NaiveDate,
+		cover: Option<Cover>,
+		config: &DownloaderConfig,
+	) {
+		// Format specific
+		if let TagWrap::Id3(id3) = tag_wrap {
+			id3.use_id3_v24(config.id3v24)
+		}
 
 		let tag = tag_wrap.get_tag();
 		tag.set_separator(&config.separator);
 		for (field, value) in tags {
-			tag.set_field(field, value);
+			match field {
+				Field::Artist => match config.artists_tag {
+					ArtistsTag::Multiple => tag.set_field(field, value),
+					ArtistsTag::Joined => tag.set_field_joined(field, value),
+					ArtistsTag::JoinedWithArtists => {
+						tag.set_field_joined(field, value.clone());
+						tag.set_field(Field::Artists, value);
+					}
+				},
+				_ => tag.set_field(field, value),
+			}
 		}
 		tag.set_release_date(date);
 		// Cover
@@ -481,60 +885,28 @@ impl DownloaderInternal {
 			tag.add_cover(&mime, data);
 		}
 		// UFID spotify track id
-		tag.add_unique_file_identifier(&track_id);
-		tag.save()?;
-		Ok(())
+		tag.add_unique_file_identifier(track_id);
 	}
 
-    
-    async fn find_alternative(session: &Session, track: Track) -> Result<Track, SpotifyError> {
-        let librespot::metadata::track::Tracks(ids) = track.alternatives;
-        for id in ids {
-            let t = Track::get(session, &id).await?;
-            if !Self::track_has_alternatives(&t) {
-                return Ok(t);
-            }
-        }
-
-        Err(SpotifyError::Unavailable)
-    }
-
-    fn track_has_alternatives(track: &Track) -> bool {
-        let librespot::metadata::track::Tracks(alts) = &track.alternatives;
-        !alts.is_empty()
-    }
-
 	/// Download track by id
 	async fn download_track(
-		session: &Session,
+		audio: &dyn AudioSource,
 		id: &str,
 		path: impl AsRef<Path>,
 		config: DownloaderConfig,
 		tx: Sender<Message>,
 		job_id: i64,
-	) -> Result<(PathBuf, AudioFormat), SpotifyError> {
-		let id = SpotifyId::from_base62(id)?;
-		let mut track = Track::get(session, &id).await?;
-
-		// Fallback if unavailable
-        if Self::track_has_alternatives(&track) {
-            track = Self::find_alternative(session, track).await?;
-        }
-        
-
-
-		// if !track.available {
-		// 	track = DownloaderInternal::find_alternative(session, track).await?;
-		// } //TODO
+		cancellations: &Cancellations,
+	) -> Result<(PathBuf, AudioFormat, Option<NormalisationData>), SpotifyError> {
+		let formats = audio.formats(id).await?;
 
 		// Quality fallback
 		let mut quality = config.quality;
-		let (mut file_id, mut file_format) = (None, None);
+		let mut file_format = None;
 		'outer: loop {
 			for format in quality.get_file_formats() {
-				if let Some(f) = track.files.get(&format) {
-					info!("{} Using {:?} format.", id.to_base62().unwrap(), format);
-					file_id = Some(f);
+				if formats.contains(&format) {
+					info!("{} Using {:?} format.", id, format);
 					file_format = Some(format);
 					break 'outer;
 				}
@@ -544,11 +916,10 @@ impl DownloaderInternal {
 				Some(q) => quality = q,
 				None => break,
 			}
-			warn!("{} Falling back to: {:?}", id.to_base62().unwrap(), quality);
+			warn!("{} Falling back to: {:?}", id, quality);
 		}
 
-		let file_id = file_id.ok_or(SpotifyError::Unavailable)?;
-		let file_format = file_format.unwrap();
+		let file_format = file_format.ok_or(SpotifyError::Unavailable)?;
 
 		// Path with extension
 		let mut audio_format: AudioFormat = file_format.into();
@@ -569,29 +940,42 @@ impl DownloaderInternal {
 
 		let path_clone = path.clone();
 
-		let key = session.audio_key().request(track.id, *file_id).await?;
-		let encrypted = AudioFile::open(session, *file_id, 1024 * 1024).await?;
-		let size = encrypted.get_stream_loader_controller()?.len();
-		// Download
-		let s = match config.convert_to_mp3 {
+		let stream = audio.open(id, file_format).await?;
+		let (decrypted, size, normalisation) = (stream.reader, stream.size, stream.normalisation);
+		// Download, MP3 sources are written as-is as reencoding only loses quality
+		let convert = config.convert_to_mp3 && !matches!(audio_format, AudioFormat::Mp3);
+		if config.convert_to_mp3 && !convert {
+			warn!("{} Already MP3, not converting or normalising", id);
+		}
+		let mut gain_db = None;
+		let s = match convert {
 			true => {
+				gain_db = normalisation
+					.as_ref()
+					.and_then(|n| config.normalisation.gain_db(n));
 				let s = DownloaderInternal::download_track_convert_stream(
 					path_clone,
-					encrypted,
-					key,
+					decrypted,
 					audio_format.clone(),
 					quality,
+					config.clone(),
+					gain_db,
 				)
 				.boxed();
 				audio_format = AudioFormat::Mp3;
 				s
 			}
-			false => DownloaderInternal::download_track_stream(path_clone, encrypted, key).boxed(),
+			false => DownloaderInternal::download_track_stream(path_clone, decrypted).boxed(),
 		};
 		pin_mut!(s);
 		// Read progress
 		let mut read = 0;
 		while let Some(result) = s.next().await {
+			// Stop reading as soon as possible
+			let result = match cancellations.is_cancelled(job_id) {
+				true => Err(SpotifyError::Cancelled),
+				false => result,
+			};
 			match result {
 				Ok(r) => {
 					read += r;
@@ -609,90 +993,100 @@ impl DownloaderInternal {
 			}
 		}
 
-		info!("Done downloading: {}", track.id.to_base62().unwrap());
-		Ok((path, audio_format))
+		info!("Done downloading: {}", id);
+		// Loudness data of the written file
+		let normalisation = match gain_db {
+			Some(gain_db) => normalisation.map(|n| n.with_gain(gain_db)),
+			None => normalisation,
+		};
+		Ok((path, audio_format, normalisation))
 	}
 
 	fn download_track_stream(
 		path: impl AsRef<Path>,
-		encrypted: AudioFile,
-		key: AudioKey,
+		decrypted: Box<dyn Read + Send>,
 	) -> impl Stream<Item = Result<usize, SpotifyError>> {
 		try_stream! {
 			let mut file = File::create(path).await?;
-			let mut decrypted = AudioDecrypt::new(Some(key), encrypted);
-			// Skip (i guess encrypted shit)
-			let mut skip: [u8; 0xa7] = [0; 0xa7];
-			let mut decrypted = tokio::task::spawn_blocking(move || {
-				match decrypted.read_exact(&mut skip) {
-					Ok(_) => Ok(decrypted),
-					Err(e) => Err(e)
-				}
-			}).await??;
-			// Custom reader loop for decrypting
-			loop {
-				// Blocking reader
-				let (d, read, buf) = tokio::task::spawn_blocking(move || {
-					let mut buf = vec![0; 1024 * 64];
-					match decrypted.read(&mut buf) {
-						Ok(r) => Ok((decrypted, r, buf)),
-						Err(e) => Err(e)
-					}
-				}).await??;
-				decrypted = d;
-				if read == 0 {
-					break;
-				}
-				file.write_all(&buf[0..read]).await?;
-				yield read;
+			// Decrypt on a single blocking thread
+			let mut reader = BlockingReader::new(decrypted);
+			while let Some(buf) = reader.next().await {
+				let buf = buf?;
+				file.write_all(&buf).await?;
+				yield buf.len();
+				reader.recycle(buf);
 			}
+			reader.finish().await?;
+			file.flush().await?;
 		}
 	}
-	/// Download and convert to MP3
+	/// Download and convert to MP3, yields bytes read from the source like the plain stream
 	fn download_track_convert_stream(
 		path: impl AsRef<Path>,
-		encrypted: AudioFile,
-		key: AudioKey,
+		decrypted: Box<dyn Read + Send>,
 		format: AudioFormat,
 		quality: Quality,
+		config: DownloaderConfig,
+		gain_db: Option<f32>,
 	) -> impl Stream<Item = Result<usize, SpotifyError>> {
 		try_stream! {
 			let mut file = File::create(path).await?;
-			let mut decrypted = AudioDecrypt::new(Some(key), encrypted);
-			// Skip (i guess encrypted shit)
-			let mut skip: [u8; 0xa7] = [0; 0xa7];
-			let decrypted = tokio::task::spawn_blocking(move || {
-				match decrypted.read_exact(&mut skip) {
-					Ok(_) => Ok(decrypted),
-					Err(e) => Err(e)
-				}
-			}).await??;
+			// Progress is the source size, not the smaller MP3
+			let source_read = Arc::new(AtomicUsize::new(0));
+			let decrypted = CountingReader {
+				inner: decrypted,
+				read: source_read.clone(),
+			};
 			// Convertor
-			let mut decrypted = tokio::task::spawn_blocking(move || {
-				AudioConverter::new(Box::new(decrypted), format, quality)
+			let converter = tokio::task::spawn_blocking(move || {
+				AudioConverter::new(
+					Box::new(decrypted),
+					format,
+					quality,
+					&config.mp3,
+					gain_db,
+					config.normalisation.limiter,
+				)
 			}).await??;
 
-			// Custom reader loop for decrypting
-			loop {
-				// Blocking reader
-				let (d, read, buf) = tokio::task::spawn_blocking(move || {
-					let mut buf = vec![0; 1024 * 64];
-					match decrypted.read(&mut buf) {
-						Ok(r) => Ok((decrypted, r, buf)),
-						Err(e) => Err(e)
-					}
-				}).await??;
-				decrypted = d;
-				if read == 0 {
-					break;
-				}
-				file.write_all(&buf[0..read]).await?;
-				yield read;
+			// Decrypt and convert on a single blocking thread
+			let mut reader = BlockingReader::new(converter);
+			let mut reported = 0;
+			while let Some(buf) = reader.next().await {
+				let buf = buf?;
+				file.write_all(&buf).await?;
+				let read = source_read.load(Ordering::Relaxed);
+				yield read - reported;
+				reported = read;
+				reader.recycle(buf);
+			}
+			let converter = reader.finish().await?;
+
+			// Replace the placeholder first frame with the final Xing/LAME header
+			let lametag = converter.lametag_frame();
+			if !lametag.is_empty() {
+				file.seek(SeekFrom::Start(0)).await?;
+				file.write_all(&lametag).await?;
 			}
+			file.flush().await?;
 		}
 	}
 }
 
+/// Counts the bytes read from the inner reader
+struct CountingReader {
+	inner: Box<dyn Read + Send>,
+	read: Arc<AtomicUsize>,
+}
+
+impl Read for CountingReader {
+	fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+		let read = self.inner.read(buf)?;
+		self.read.fetch_add(read, Ordering::Relaxed);
+		Ok(read)
+	}
+}
+
 #[derive(Debug, Clone)]
 pub enum AudioFormat {
 	Ogg,
@@ -716,6 +1110,24 @@ impl AudioFormat {
 		}
 		.to_string()
 	}
+
+	/// Get format from file extension
+	pub fn from_path(path: impl AsRef<Path>) -> AudioFormat {
+		match path
+			.as_ref()
+			.extension()
+			.and_then(|e| e.to_str())
+			.map(|e| e.to_lowercase())
+			.as_deref()
+		{
+			Some("ogg") => AudioFormat::Ogg,
+			Some("m4a") => AudioFormat::Aac,
+			Some("mp3") => AudioFormat::Mp3,
+			Some("mp4") => AudioFormat::Mp4,
+			Some("flac") => AudioFormat::Flac,
+			_ => AudioFormat::Unknown,
+		}
+	}
 }
 
 impl From<FileFormat> for AudioFormat {
@@ -770,6 +1182,85 @@ impl Quality {
 pub struct DownloadJob {
 	pub id: i64,
 	pub track_id: String,
+	pub album_id: Option<String>,
+	/// Amount of tracks of the album in the queue, 0 if not queued as a whole album
+	pub album_tracks: usize,
+}
+
+/// Downloaded track with its measured loudness
+type AnalyzedTrack = (PathBuf, AudioFormat, TrackLoudness);
+
+/// Tracks of an album, album ReplayGain is written once all of them are finished
+struct PendingAlbum {
+	total: usize,
+	/// IDs of finished downloads, so retried tracks aren't counted twice
+	finished: HashSet<i64>,
+	tracks: HashMap<i64, AnalyzedTrack>,
+}
+
+/// Albums in the queue by ID, shared with the communication thread for cancelled tracks
+#[derive(Clone, Default)]
+struct Albums(Arc<Mutex<HashMap<String, PendingAlbum>>>);
+
+impl Albums {
+	/// Mark track finished, writing album ReplayGain once every track of the album is analyzed
+	///
+	/// `track` is None if the download failed, was skipped or was cancelled. Album ReplayGain
+	/// of a subset would be wrong, so it's only written if no track is missing. Finished albums
+	/// are kept, so a successful retry of the missing track still writes it.
+	async fn finish(
+		&self,
+		job: &DownloadJob,
+		track: Option<AnalyzedTrack>,
+		config: &DownloaderConfig,
+	) -> Result<(), SpotifyError> {
+		let album_id = match &job.album_id {
+			Some(album_id) if job.album_tracks > 0 => album_id,
+			_ => return Ok(()),
+		};
+		let tracks: Vec<AnalyzedTrack> = {
+			let mut albums = self.0.lock().await;
+			let album = albums
+				.entry(album_id.to_string())
+				.or_insert_with(|| PendingAlbum {
+					total: job.album_tracks,
+					finished: HashSet::new(),
+					tracks: HashMap::new(),
+				});
+			album.finished.insert(job.id);
+			if let Some(track) = track {
+				album.tracks.insert(job.id, track);
+			}
+			if album.finished.len() < album.total {
+				return Ok(());
+			}
+			if album.tracks.len() < album.total {
+				warn!(
+					"{} Skipping album ReplayGain, only {} of {} tracks analyzed",
+					album_id,
+					album.tracks.len(),
+					album.total
+				);
+				return Ok(());
+			}
+			album.tracks.values().cloned().collect()
+		};
+
+		let tags = replaygain::album_tags(
+			&tracks
+				.iter()
+				.map(|(_, _, loudness)| loudness.clone())
+				.collect::<Vec<TrackLoudness>>(),
+		);
+		for (path, format, _) in tracks {
+			let (tags, config) = (tags.clone(), config.clone());
+			tokio::task::spawn_blocking(move || {
+				DownloaderInternal::write_custom_tags(path, format, tags, &config)
+			})
+			.await??;
+		}
+		Ok(())
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -778,10 +1269,20 @@ pub enum Message {
 	GetJob,
 	// Update state of download
 	UpdateState(i64, DownloadState),
+	// Set path of downloaded file
+	UpdatePath(i64, PathBuf),
 	//add to download
 	AddToQueue(Vec<Download>),
 	// Get all downloads to UI
 	GetDownloads,
+	// Get statistics, answered on the given channel
+	GetStats(Sender<DownloadStats>),
+	// Stop download
+	Cancel(i64),
+	// Queue failed download again
+	Retry(i64),
+	// Move download to the front of the queue
+	Prioritize(i64),
 }
 
 #[derive(Debug, Clone)]
@@ -789,14 +1290,35 @@ pub enum Response {
 	Downloads(Vec<Download>),
 }
 
+/// Track in the download queue
 #[derive(Debug, Clone)]
 pub struct Download {
+	/// Assigned when added to the queue
 	pub id: i64,
 	pub track_id: String,
+	pub album_id: Option<String>,
 	pub title: String,
 	pub state: DownloadState,
+	/// Downloaded file, once known
+	pub path: Option<PathBuf>,
+	/// URI the track was queued from, for example its album or playlist
+	pub source: Option<String>,
+	/// Queued along with the rest of its album, required for album ReplayGain
+	pub whole_album: bool,
 }
 
+impl Download {
+	/// Create from track of album
+	pub fn from_album_track(track: aspotify::TrackSimplified, album_id: &str) -> Download {
+		Download {
+			album_id: Some(album_id.to_string()),
+			whole_album: true,
+			..track.into()
+		}
+	}
+}
+
+/// Track found by `Downloader::handle_input`
 #[derive(Debug, Clone)]
 pub struct SearchResult {
 	pub track_id: String,
@@ -819,8 +1341,12 @@ impl From<aspotify::Track> for Download {
 		Download {
 			id: 0,
 			track_id: val.id.unwrap(),
+			album_id: val.album.id,
 			title: val.name,
 			state: DownloadState::None,
+			path: None,
+			source: None,
+			whole_album: false,
 		}
 	}
 }
@@ -830,8 +1356,12 @@ impl From<aspotify::TrackSimplified> for Download {
 		Download {
 			id: 0,
 			track_id: val.id.unwrap(),
+			album_id: None,
 			title: val.name,
 			state: DownloadState::None,
+			path: None,
+			source: None,
+			whole_album: false,
 		}
 	}
 }
@@ -841,15 +1371,71 @@ impl From<Download> for DownloadJob {
 		DownloadJob {
 			id: val.id,
 			track_id: val.track_id,
+			album_id: val.album_id,
+			album_tracks: 0,
+		}
+	}
+}
+
+/// Change of a download, sent to subscribers
+///
+/// All events except `Queued` and `Lagged` carry the ID of the download.
+#[derive(Debug, Clone)]
+pub enum DownloadEvent {
+	/// Added to the queue
+	Queued(Download),
+	/// Started downloading
+	Started(i64),
+	/// Bytes read and total bytes
+	Progress(i64, usize, usize),
+	/// Any other state change
+	StateChanged(i64, DownloadState),
+	/// Done, with the path of the file
+	Finished(i64, PathBuf),
+	Error(i64, SpotifyError),
+	/// Subscriber fell behind and missed this many events
+	Lagged(u64),
+}
+
+impl DownloadEvent {
+	/// ID of the download
+	pub fn id(&self) -> Option<i64> {
+		match self {
+			DownloadEvent::Queued(download) => Some(download.id),
+			DownloadEvent::Started(id)
+			| DownloadEvent::Progress(id, _, _)
+			| DownloadEvent::StateChanged(id, _)
+			| DownloadEvent::Finished(id, _)
+			| DownloadEvent::Error(id, _) => Some(*id),
+			DownloadEvent::Lagged(_) => None,
+		}
+	}
+
+	/// New state of the download
+	pub fn state(&self) -> Option<DownloadState> {
+		match self {
+			DownloadEvent::Queued(download) => Some(download.state.clone()),
+			DownloadEvent::Progress(_, read, total) => {
+				Some(DownloadState::Downloading(*read, *total))
+			}
+			DownloadEvent::StateChanged(_, state) => Some(state.clone()),
+			DownloadEvent::Finished(_, _) => Some(DownloadState::Done),
+			DownloadEvent::Error(_, e) => Some(DownloadState::Error(e.clone())),
+			DownloadEvent::Started(_) | DownloadEvent::Lagged(_) => None,
 		}
 	}
 }
 
+/// Progress of a download
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum DownloadState {
+	/// Waiting in the queue
 	None,
+	/// Picked up by a worker
 	Lock,
+	/// Bytes read and total bytes
 	Downloading(usize, usize),
+	/// Checking, normalising and tagging
 	Post,
 	Done,
 	Error(SpotifyError),
@@ -879,6 +1465,7 @@ impl Display for Quality {
 	}
 }
 
+/// Download settings, stored in the settings file
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloaderConfig {
 	pub concurrent_downloads: usize,
@@ -887,12 +1474,37 @@ pub struct DownloaderConfig {
 	pub filename_template: String,
 	pub id3v24: bool,
 	pub convert_to_mp3: bool,
+	/// Encoder settings used when converting to MP3
+	#[serde(default)]
+	pub mp3: Mp3Config,
+	/// Volume normalisation using Spotify's loudness data, requires `convert_to_mp3`
+	#[serde(default)]
+	pub normalisation: NormalisationConfig,
 	pub separator: String,
+	#[serde(default)]
+	pub artists_tag: ArtistsTag,
 	pub skip_existing: bool,
+	#[serde(default)]
+	pub cover: CoverConfig,
+	/// Analyze loudness and write ReplayGain tags
+	#[serde(default)]
+	pub replaygain: bool,
+	/// Write Spotify's loudness data as ReplayGain tags, ignored if `replaygain` is enabled
+	#[serde(default)]
+	pub spotify_replaygain: bool,
+	/// Decode downloaded files and fail if they are shorter than the track
+	#[serde(default)]
+	pub check_duration: bool,
+	/// Check downloaded files are playable
+	#[serde(default)]
+	pub verify: VerifyMode,
+	/// How often failed downloads are retried, if the error is retryable
+	#[serde(default)]
+	pub retries: usize,
 }
 
 impl DownloaderConfig {
-	// Create new instance
+	/// Create new instance with default settings
 	pub fn new() -> DownloaderConfig {
 		DownloaderConfig {
 			concurrent_downloads: 4,
@@ -901,8 +1513,41 @@ impl DownloaderConfig {
 			filename_template: "%artist% - %title%".to_string(),
 			id3v24: true,
 			convert_to_mp3: false,
+			mp3: Mp3Config::default(),
+			normalisation: NormalisationConfig::default(),
 			separator: ", ".to_string(),
+			artists_tag: ArtistsTag::default(),
 			skip_existing: true,
+			cover: CoverConfig::default(),
+			replaygain: false,
+			spotify_replaygain: false,
+			check_duration: false,
+			verify: VerifyMode::Off,
+			retries: 0,
 		}
 	}
+
+	/// Check for invalid settings before downloading anything
+	pub fn validate(&self) -> Result<(), SpotifyError> {
+		self.mp3.validate()?;
+		self.normalisation.validate()?;
+		if self.normalisation.mode != NormalisationMode::Off && !self.convert_to_mp3 {
+			return Err(SpotifyError::InvalidConfig(
+				"normalisation requires convert_to_mp3".to_string(),
+			));
+		}
+		Ok(())
+	}
+}
+
+/// How multiple artists are written to the artist tag
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum ArtistsTag {
+	/// One value per artist (null separated in ID3v2.4, joined with separator in ID3v2.3)
+	#[default]
+	Multiple,
+	/// Single value joined with separator
+	Joined,
+	/// Joined artist tag and a separate multi-valued `ARTISTS` tag
+	JoinedWithArtists,
 }
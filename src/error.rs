@@ -22,6 +22,15 @@ pub enum SpotifyError {
     NotConnected,
     UnknownPacket(u8),
 	AlreadyDownloaded,
+	InvalidConfig(String),
+	DecodeError(String),
+	/// Decoded and expected duration in ms
+	Truncated(u64, u64),
+	VerifyError(String),
+	/// Seconds of Retry-After, if known
+	RateLimited(Option<u64>),
+	/// Stopped by the user
+	Cancelled,
 }
 
 impl std::error::Error for SpotifyError {}
@@ -48,11 +57,73 @@ impl fmt::Display for SpotifyError {
             SpotifyError::NotConnected => write!(f, "Not Connected"),
             SpotifyError::UnknownPacket(e) => write!(f, "Unknown Packet: {}", e),
 			SpotifyError::AlreadyDownloaded => write!(f, "Already Downloaded"),
+			SpotifyError::InvalidConfig(e) => write!(f, "Invalid Config: {}", e),
+			SpotifyError::DecodeError(e) => write!(f, "Decode Error: {}", e),
+			SpotifyError::VerifyError(e) => write!(f, "Verify Error: {}", e),
+			SpotifyError::RateLimited(Some(s)) => write!(f, "Rate Limited, retry after {}s", s),
+			SpotifyError::RateLimited(None) => write!(f, "Rate Limited"),
+			SpotifyError::Cancelled => write!(f, "Cancelled"),
+			SpotifyError::Truncated(d, e) => {
+				write!(f, "Truncated: decoded {}ms, expected {}ms", d, e)
+			}
 		}
 	}
 }
+impl SpotifyError {
+	/// Whether downloading again might succeed
+	pub fn is_retryable(&self) -> bool {
+		matches!(
+			self,
+			SpotifyError::MercuryError
+				| SpotifyError::ChannelError
+				| SpotifyError::AudioKeyError
+				| SpotifyError::Reqwest(_)
+				| SpotifyError::DecodeError(_)
+				| SpotifyError::Truncated(_, _)
+				| SpotifyError::VerifyError(_)
+				| SpotifyError::RateLimited(_)
+		)
+	}
+
+	/// Stable machine readable name of the error
+	pub fn kind(&self) -> &'static str {
+		match self {
+			SpotifyError::Error(_) => "error",
+			SpotifyError::IoError(_, _) => "io",
+			SpotifyError::MercuryError => "mercury",
+			SpotifyError::AuthenticationError => "authentication",
+			SpotifyError::Unavailable => "unavailable",
+			SpotifyError::SpotifyIdError => "invalid_id",
+			SpotifyError::ChannelError => "channel",
+			SpotifyError::AudioKeyError => "audio_key",
+			SpotifyError::LameConverterError(_) => "converter",
+			SpotifyError::JoinError => "join",
+			SpotifyError::ASpotify(_) => "web_api",
+			SpotifyError::Serde(_, _, _) => "serde",
+			SpotifyError::InvalidUri => "invalid_uri",
+			SpotifyError::ParseError(_) => "parse",
+			SpotifyError::ID3Error(_, _) => "id3",
+			SpotifyError::Reqwest(_) => "http",
+			SpotifyError::InvalidFormat => "invalid_format",
+			SpotifyError::NotConnected => "not_connected",
+			SpotifyError::UnknownPacket(_) => "unknown_packet",
+			SpotifyError::AlreadyDownloaded => "already_downloaded",
+			SpotifyError::InvalidConfig(_) => "invalid_config",
+			SpotifyError::DecodeError(_) => "decode",
+			SpotifyError::Truncated(_, _) => "truncated",
+			SpotifyError::VerifyError(_) => "verify",
+			SpotifyError::RateLimited(_) => "rate_limited",
+			SpotifyError::Cancelled => "cancelled",
+		}
+	}
+}
+
 impl From<std::io::Error> for SpotifyError {
 	fn from(e: std::io::Error) -> Self {
+		// Errors passed through `Read` impls
+		if let Some(e) = e.get_ref().and_then(|e| e.downcast_ref::<SpotifyError>()) {
+			return e.clone();
+		}
 		Self::IoError(e.kind(), e.to_string())
 	}
 }
@@ -143,6 +214,6 @@ impl From<reqwest::Error> for SpotifyError {
 
 impl From<lewton::VorbisError> for SpotifyError {
 	fn from(e: lewton::VorbisError) -> Self {
-		SpotifyError::Error(format!("Lewton: {}", e))
+		SpotifyError::DecodeError(format!("Lewton: {}", e))
 	}
 }
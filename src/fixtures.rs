@@ -0,0 +1,254 @@
+use aspotify::{Album, Artist, Playlist, PlaylistItemType, Track, TrackSimplified};
+use async_trait::async_trait;
+use librespot::protocol::metadata::audio_file::Format as FileFormat;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::downloader::AudioFormat;
+use crate::error::SpotifyError;
+use crate::metadata::MetadataProvider;
+use crate::source::{AudioSource, AudioStream};
+use crate::spotify::{Spotify, SpotifyItem};
+use crate::webapi::{collect_pages, Page};
+
+/// Formats which can be served from local files
+const LOCAL_FORMATS: [FileFormat; 7] = [
+	FileFormat::OGG_VORBIS_96,
+	FileFormat::OGG_VORBIS_160,
+	FileFormat::OGG_VORBIS_320,
+	FileFormat::MP3_96,
+	FileFormat::MP3_160,
+	FileFormat::MP3_256,
+	FileFormat::MP3_320,
+];
+
+/// Metadata served from memory, for running without network
+///
+/// `from_dir` loads Web API JSON responses from a directory laid out as:
+/// `tracks/<id>.json`, `albums/<id>.json`, `playlists/<id>.json`,
+/// `artists/<id>.json` and `artists/<id>.albums.json` (array of album IDs).
+#[derive(Debug, Clone, Default)]
+pub struct FixtureMetadata {
+	tracks: HashMap<String, Track>,
+	albums: HashMap<String, Album>,
+	playlists: HashMap<String, Playlist>,
+	artists: HashMap<String, (Artist, Vec<String>)>,
+}
+
+impl FixtureMetadata {
+	/// Create empty instance
+	pub fn new() -> FixtureMetadata {
+		FixtureMetadata::default()
+	}
+
+	/// Load all fixtures from directory ( BLOCKING )
+	pub fn from_dir(path: impl AsRef<Path>) -> Result<FixtureMetadata, SpotifyError> {
+		let path = path.as_ref();
+		let mut fixtures = FixtureMetadata::new();
+		for track in load_all::<Track>(&path.join("tracks"))? {
+			fixtures.add_track(track);
+		}
+		for album in load_all::<Album>(&path.join("albums"))? {
+			fixtures.add_album(album);
+		}
+		for playlist in load_all::<Playlist>(&path.join("playlists"))? {
+			fixtures.add_playlist(playlist);
+		}
+		for artist in load_all::<Artist>(&path.join("artists"))? {
+			let albums_path = path
+				.join("artists")
+				.join(format!("{}.albums.json", artist.id));
+			let albums = match albums_path.is_file() {
+				true => load(&albums_path)?,
+				false => vec![],
+			};
+			fixtures.add_artist(artist, albums);
+		}
+		Ok(fixtures)
+	}
+
+	pub fn add_track(&mut self, track: Track) {
+		if let Some(id) = track.id.clone() {
+			self.tracks.insert(id, track);
+		}
+	}
+
+	pub fn add_album(&mut self, album: Album) {
+		self.albums.insert(album.id.clone(), album);
+	}
+
+	pub fn add_playlist(&mut self, playlist: Playlist) {
+		self.playlists.insert(playlist.id.clone(), playlist);
+	}
+
+	/// Add artist with the IDs of their albums
+	pub fn add_artist(&mut self, artist: Artist, album_ids: Vec<String>) {
+		self.artists.insert(artist.id.clone(), (artist, album_ids));
+	}
+}
+
+#[async_trait]
+impl MetadataProvider for FixtureMetadata {
+	async fn resolve_uri(&self, uri: &str) -> Result<SpotifyItem, SpotifyError> {
+		let uri = Spotify::parse_uri(uri)?;
+		let parts = uri.split(':').skip(1).collect::<Vec<&str>>();
+		let id = parts[1];
+		match parts[0] {
+			"track" => Ok(SpotifyItem::Track(self.get_track(id).await?)),
+			"album" => Ok(SpotifyItem::Album(self.get_album(id).await?)),
+			"playlist" => self
+				.playlists
+				.get(id)
+				.map(|p| SpotifyItem::Playlist(p.clone()))
+				.ok_or(SpotifyError::Unavailable),
+			"artist" => self
+				.artists
+				.get(id)
+				.map(|(a, _)| SpotifyItem::Artist(a.clone()))
+				.ok_or(SpotifyError::Unavailable),
+			_ => Ok(SpotifyItem::Other(uri.to_string())),
+		}
+	}
+
+	async fn search(&self, query: &str) -> Result<Vec<Track>, SpotifyError> {
+		let query = query.to_lowercase();
+		let mut tracks: Vec<Track> = self
+			.tracks
+			.values()
+			.filter(|t| t.name.to_lowercase().contains(&query))
+			.cloned()
+			.collect();
+		tracks.sort_by(|a, b| a.name.cmp(&b.name));
+		Ok(tracks)
+	}
+
+	async fn full_playlist(&self, id: &str) -> Result<Vec<Track>, SpotifyError> {
+		let playlist = self.playlists.get(id).ok_or(SpotifyError::Unavailable)?;
+		// Paged like the Web API
+		let items = &playlist.tracks.items;
+		let items = collect_pages(100, |offset, limit| {
+			let page = Page {
+				items: items.iter().skip(offset).take(limit).cloned().collect(),
+				total: items.len(),
+			};
+			async move { Ok(page) }
+		})
+		.await?;
+		Ok(items
+			.into_iter()
+			.filter_map(|i| match i.item {
+				Some(PlaylistItemType::Track(t)) => Some(t),
+				_ => None,
+			})
+			.collect())
+	}
+
+	async fn full_album(&self, id: &str) -> Result<Vec<TrackSimplified>, SpotifyError> {
+		Ok(self.get_album(id).await?.tracks.items)
+	}
+
+	async fn full_artist(&self, id: &str) -> Result<Vec<(String, TrackSimplified)>, SpotifyError> {
+		let (_, album_ids) = self.artists.get(id).ok_or(SpotifyError::Unavailable)?;
+		let mut items = vec![];
+		for album_id in album_ids {
+			for track in self.full_album(album_id).await? {
+				items.push((album_id.to_string(), track));
+			}
+		}
+		Ok(items)
+	}
+
+	async fn get_track(&self, id: &str) -> Result<Track, SpotifyError> {
+		self.tracks
+			.get(id)
+			.cloned()
+			.ok_or(SpotifyError::Unavailable)
+	}
+
+	async fn get_album(&self, id: &str) -> Result<Album, SpotifyError> {
+		self.albums
+			.get(id)
+			.cloned()
+			.ok_or(SpotifyError::Unavailable)
+	}
+}
+
+/// Audio served from local Ogg and MP3 files
+///
+/// Files are laid out as `<track id>/<format>.<extension>`,
+/// for example `<track id>/OGG_VORBIS_160.ogg` or `<track id>/MP3_320.mp3`.
+/// Ogg files are plain Vorbis, without Spotify's header.
+#[derive(Debug, Clone)]
+pub struct LocalAudioSource {
+	path: PathBuf,
+}
+
+impl LocalAudioSource {
+	/// Create new instance serving files from directory
+	pub fn new(path: impl AsRef<Path>) -> LocalAudioSource {
+		LocalAudioSource {
+			path: path.as_ref().to_owned(),
+		}
+	}
+
+	/// Path of track in format
+	fn file(&self, track_id: &str, format: FileFormat) -> PathBuf {
+		let extension = AudioFormat::from(format).extension();
+		self.path
+			.join(track_id)
+			.join(format!("{:?}.{}", format, extension))
+	}
+}
+
+#[async_trait]
+impl AudioSource for LocalAudioSource {
+	async fn formats(&self, track_id: &str) -> Result<Vec<FileFormat>, SpotifyError> {
+		let mut formats = vec![];
+		for format in LOCAL_FORMATS {
+			let path = self.file(track_id, format);
+			if tokio::fs::metadata(path).await.is_ok() {
+				formats.push(format);
+			}
+		}
+		Ok(formats)
+	}
+
+	async fn open(&self, track_id: &str, format: FileFormat) -> Result<AudioStream, SpotifyError> {
+		let path = self.file(track_id, format);
+		if !path.is_file() {
+			return Err(SpotifyError::Unavailable);
+		}
+		let size = tokio::fs::metadata(&path).await?.len() as usize;
+		Ok(AudioStream {
+			reader: Box::new(std::fs::File::open(path)?),
+			size,
+			normalisation: None,
+		})
+	}
+}
+
+/// Load JSON file ( BLOCKING )
+fn load<T: DeserializeOwned>(path: &Path) -> Result<T, SpotifyError> {
+	Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+/// Load all `<id>.json` files of directory, missing directory is empty ( BLOCKING )
+fn load_all<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, SpotifyError> {
+	if !path.is_dir() {
+		return Ok(vec![]);
+	}
+	let mut items = vec![];
+	for entry in std::fs::read_dir(path)? {
+		let path = entry?.path();
+		let name = path
+			.file_name()
+			.and_then(|n| n.to_str())
+			.unwrap_or_default();
+		// Album lists of artists
+		if name.ends_with(".json") && name.matches('.').count() == 1 {
+			items.push(load(&path)?);
+		}
+	}
+	Ok(items)
+}
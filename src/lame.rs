@@ -0,0 +1,226 @@
+//! Bindings to libmp3lame
+//!
+//! The `lame` crate only exposes channels, sample rate, quality and a constant bitrate, and
+//! keeps its handle private. VBR/ABR, resampling, stereo mode, flushing the encoder and the
+//! Xing/LAME header can't be reached through it, so the few functions needed are declared here.
+//! The library is linked the same way the crate did, `libmp3lame-dev` or `mp3lame.lib`.
+
+use std::os::raw::{c_int, c_short, c_uchar};
+use std::ptr;
+
+use crate::error::SpotifyError;
+use crate::error::SpotifyError::LameConverterError;
+
+#[repr(C)]
+struct LameGlobalFlags {
+	_private: [u8; 0],
+}
+
+// MPEG_mode
+const JOINT_STEREO: c_int = 1;
+const STEREO: c_int = 0;
+// vbr_mode
+const VBR_OFF: c_int = 0;
+const VBR_ABR: c_int = 3;
+const VBR_MTRH: c_int = 4;
+
+#[link(name = "mp3lame")]
+extern "C" {
+	fn lame_init() -> *mut LameGlobalFlags;
+	fn lame_close(gfp: *mut LameGlobalFlags) -> c_int;
+	fn lame_set_num_channels(gfp: *mut LameGlobalFlags, channels: c_int) -> c_int;
+	fn lame_set_in_samplerate(gfp: *mut LameGlobalFlags, rate: c_int) -> c_int;
+	fn lame_set_out_samplerate(gfp: *mut LameGlobalFlags, rate: c_int) -> c_int;
+	fn lame_set_quality(gfp: *mut LameGlobalFlags, quality: c_int) -> c_int;
+	fn lame_set_mode(gfp: *mut LameGlobalFlags, mode: c_int) -> c_int;
+	fn lame_set_brate(gfp: *mut LameGlobalFlags, kbps: c_int) -> c_int;
+	fn lame_set_VBR(gfp: *mut LameGlobalFlags, mode: c_int) -> c_int;
+	fn lame_set_VBR_q(gfp: *mut LameGlobalFlags, quality: c_int) -> c_int;
+	fn lame_set_VBR_mean_bitrate_kbps(gfp: *mut LameGlobalFlags, kbps: c_int) -> c_int;
+	fn lame_set_bWriteVbrTag(gfp: *mut LameGlobalFlags, write: c_int) -> c_int;
+	fn lame_init_params(gfp: *mut LameGlobalFlags) -> c_int;
+	fn lame_encode_buffer(
+		gfp: *mut LameGlobalFlags,
+		buffer_l: *const c_short,
+		buffer_r: *const c_short,
+		samples: c_int,
+		mp3buf: *mut c_uchar,
+		mp3buf_size: c_int,
+	) -> c_int;
+	fn lame_encode_flush(gfp: *mut LameGlobalFlags, mp3buf: *mut c_uchar, size: c_int) -> c_int;
+	fn lame_get_lametag_frame(
+		gfp: *const LameGlobalFlags,
+		buffer: *mut c_uchar,
+		size: usize,
+	) -> usize;
+}
+
+/// Minimal safe wrapper around libmp3lame
+///
+/// Invariants every `unsafe` block relies on: `ptr` is non-null, was returned by `lame_init`,
+/// is owned by this instance only and is closed exactly once in `Drop`. Buffers are always
+/// passed together with their real length.
+pub struct Lame {
+	ptr: *mut LameGlobalFlags,
+}
+
+// SAFETY: libmp3lame keeps all state in the handle and uses no thread-locals, so the handle can
+// be used from another thread. It's not `Sync`, every call goes through `&mut self` or happens
+// after encoding (`lametag_frame`), so it's never used from two threads at once.
+unsafe impl Send for Lame {}
+
+impl Lame {
+	/// Create new encoder
+	pub fn new() -> Result<Lame, SpotifyError> {
+		// SAFETY: No arguments, null is handled below
+		let ptr = unsafe { lame_init() };
+		if ptr.is_null() {
+			return Err(LameConverterError("Init".to_string()));
+		}
+		Ok(Lame { ptr })
+	}
+
+	/// Convert lame return code
+	fn check(result: c_int, name: &str) -> Result<(), SpotifyError> {
+		match result {
+			r if r < 0 => Err(LameConverterError(name.to_string())),
+			_ => Ok(()),
+		}
+	}
+
+	pub fn set_channels(&mut self, channels: u8) -> Result<(), SpotifyError> {
+		Lame::check(
+			unsafe { lame_set_num_channels(self.ptr, channels as c_int) },
+			"Channels",
+		)
+	}
+
+	pub fn set_sample_rate(&mut self, rate: u32) -> Result<(), SpotifyError> {
+		Lame::check(
+			unsafe { lame_set_in_samplerate(self.ptr, rate as c_int) },
+			"Sample rate",
+		)
+	}
+
+	/// Resample to rate
+	pub fn set_out_sample_rate(&mut self, rate: u32) -> Result<(), SpotifyError> {
+		Lame::check(
+			unsafe { lame_set_out_samplerate(self.ptr, rate as c_int) },
+			"Output sample rate",
+		)
+	}
+
+	/// Algorithm quality, 0 = best, 9 = worst
+	pub fn set_quality(&mut self, quality: u8) -> Result<(), SpotifyError> {
+		Lame::check(
+			unsafe { lame_set_quality(self.ptr, quality as c_int) },
+			"Quality",
+		)
+	}
+
+	pub fn set_joint_stereo(&mut self, joint_stereo: bool) -> Result<(), SpotifyError> {
+		let mode = match joint_stereo {
+			true => JOINT_STEREO,
+			false => STEREO,
+		};
+		Lame::check(unsafe { lame_set_mode(self.ptr, mode) }, "Mode")
+	}
+
+	/// Constant bitrate
+	pub fn set_cbr(&mut self, kbps: u32) -> Result<(), SpotifyError> {
+		Lame::check(unsafe { lame_set_VBR(self.ptr, VBR_OFF) }, "VBR")?;
+		Lame::check(
+			unsafe { lame_set_brate(self.ptr, kbps as c_int) },
+			"Bitrate",
+		)
+	}
+
+	/// Average bitrate
+	pub fn set_abr(&mut self, kbps: u32) -> Result<(), SpotifyError> {
+		Lame::check(unsafe { lame_set_VBR(self.ptr, VBR_ABR) }, "VBR")?;
+		Lame::check(
+			unsafe { lame_set_VBR_mean_bitrate_kbps(self.ptr, kbps as c_int) },
+			"Bitrate",
+		)
+	}
+
+	/// Variable bitrate, 0 = V0 (best) to 9 = V9
+	pub fn set_vbr(&mut self, quality: u8) -> Result<(), SpotifyError> {
+		Lame::check(unsafe { lame_set_VBR(self.ptr, VBR_MTRH) }, "VBR")?;
+		Lame::check(
+			unsafe { lame_set_VBR_q(self.ptr, quality as c_int) },
+			"VBR quality",
+		)
+	}
+
+	/// Reserve the first frame for the Xing/LAME header
+	pub fn set_write_vbr_tag(&mut self, write: bool) -> Result<(), SpotifyError> {
+		Lame::check(
+			unsafe { lame_set_bWriteVbrTag(self.ptr, write as c_int) },
+			"VBR tag",
+		)
+	}
+
+	pub fn init_params(&mut self) -> Result<(), SpotifyError> {
+		Lame::check(unsafe { lame_init_params(self.ptr) }, "Init")
+	}
+
+	/// Encode planar samples, returns the amount of bytes written into `out`
+	pub fn encode(
+		&mut self,
+		left: &[i16],
+		right: &[i16],
+		out: &mut [u8],
+	) -> Result<usize, SpotifyError> {
+		if left.len() != right.len() {
+			return Err(LameConverterError("Channel length mismatch".to_string()));
+		}
+		// SAFETY: Both channels hold `left.len()` samples, LAME writes at most `out.len()` bytes
+		let result = unsafe {
+			lame_encode_buffer(
+				self.ptr,
+				left.as_ptr(),
+				right.as_ptr(),
+				left.len() as c_int,
+				out.as_mut_ptr(),
+				out.len() as c_int,
+			)
+		};
+		match result {
+			-1 => Err(LameConverterError("Output buffer too small".to_string())),
+			r if r < 0 => Err(LameConverterError(format!("Encode: {}", r))),
+			r => Ok(r as usize),
+		}
+	}
+
+	/// Encode remaining buffered samples and padding, returns the amount of bytes written into `out`
+	pub fn flush(&mut self, out: &mut [u8]) -> Result<usize, SpotifyError> {
+		// SAFETY: LAME writes at most `out.len()` bytes
+		let result = unsafe { lame_encode_flush(self.ptr, out.as_mut_ptr(), out.len() as c_int) };
+		match result {
+			-1 => Err(LameConverterError("Output buffer too small".to_string())),
+			r if r < 0 => Err(LameConverterError(format!("Flush: {}", r))),
+			r => Ok(r as usize),
+		}
+	}
+
+	/// Xing/LAME header frame with duration, encoder delay and padding, call after `flush`
+	pub fn lametag_frame(&self) -> Vec<u8> {
+		// Returns the required size if the buffer is too small
+		// SAFETY: Nothing is written with size 0, then exactly `size` bytes are available
+		let size = unsafe { lame_get_lametag_frame(self.ptr, ptr::null_mut(), 0) };
+		let mut frame = vec![0; size];
+		let written = unsafe { lame_get_lametag_frame(self.ptr, frame.as_mut_ptr(), size) };
+		frame.truncate(written);
+		frame
+	}
+}
+
+impl Drop for Lame {
+	fn drop(&mut self) {
+		// SAFETY: Only place the handle is closed, it isn't used afterwards
+		unsafe {
+			lame_close(self.ptr);
+		}
+	}
+}
@@ -0,0 +1,73 @@
+//! Download songs from Spotify.
+//!
+//! [`Downloader`] queues tracks, albums, playlists and artists and downloads them in the
+//! background, converting and tagging them as configured in [`DownloaderConfig`].
+//! Metadata and audio come from a [`MetadataProvider`] and an [`AudioSource`],
+//! [`Spotify`] and [`LibrespotSource`] by default.
+//!
+//! ```no_run
+//! use down_on_spot::{DownloadState, Downloader, DownloaderConfig, Spotify};
+//!
+//! # async fn run() -> Result<(), down_on_spot::SpotifyError> {
+//! let spotify = Spotify::new("username", "password", "client_id", "client_secret", None).await?;
+//! let downloader = Downloader::new(DownloaderConfig::new(), spotify);
+//! downloader
+//! 	.add_uri("https://open.spotify.com/album/4aawyAB9vmqN3uQ7FjRGTy")
+//! 	.await?;
+//! for download in downloader.get_downloads().await {
+//! 	if let DownloadState::Error(e) = download.state {
+//! 		println!("{}: {}", download.title, e);
+//! 	}
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Tags of downloaded files can be read and written with the [`tag`] module.
+
+#[macro_use]
+extern crate log;
+
+mod converter;
+mod cover;
+mod decoder;
+mod downloader;
+mod error;
+#[cfg(feature = "fixtures")]
+mod fixtures;
+mod lame;
+mod metadata;
+mod normalisation;
+mod ratelimit;
+mod reader;
+mod reorganize;
+mod replaygain;
+mod retag;
+mod source;
+mod spotify;
+mod stats;
+pub mod tag;
+mod verify;
+mod webapi;
+
+pub use converter::{BitrateMode, Mp3Config};
+pub use cover::{CoverConfig, CoverSize};
+pub use downloader::{
+	ArtistsTag, AudioFormat, Download, DownloadEvent, DownloadState, Downloader, DownloaderConfig,
+	Quality, SearchResult,
+};
+pub use error::SpotifyError;
+#[cfg(feature = "fixtures")]
+pub use fixtures::{FixtureMetadata, LocalAudioSource};
+/// Audio file formats of Spotify
+pub use librespot::protocol::metadata::audio_file::Format as FileFormat;
+pub use metadata::MetadataProvider;
+pub use normalisation::{NormalisationConfig, NormalisationData, NormalisationMode};
+#[cfg(feature = "bench")]
+pub use reader::BlockingReader;
+pub use reorganize::{Collision, Move, Plan, PlaylistUpdate, Reorganizer, UndoLog};
+pub use retag::{Retagger, TagChange};
+pub use source::{AudioSource, AudioStream, LibrespotSource};
+pub use spotify::{Spotify, SpotifyItem};
+pub use stats::{DownloadStats, StageTime};
+pub use verify::VerifyMode;
@@ -2,57 +2,116 @@
 extern crate log;
 
 mod arg;
-mod converter;
-mod downloader;
-mod error;
+mod output;
+mod report;
 mod settings;
-mod spotify;
-mod tag;
+mod tui;
 
-use arg::Args;
+use arg::{Args, Commands, OutputFormat};
 use async_std::task;
 use colored::Colorize;
-use downloader::{DownloadState, Downloader};
-use error::SpotifyError;
+use down_on_spot::{
+	Downloader, DownloaderConfig, Plan, Reorganizer, Retagger, Spotify, SpotifyError, UndoLog,
+};
 use librespot::core::spotify_id::SpotifyIdResult;
+use report::Report;
 use settings::Settings;
-use spotify::Spotify;
-use std::time::{Duration, Instant};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Exit status of the process, documented in the README
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exit {
+	/// Everything downloaded or skipped
+	Success = 0,
+	/// Some downloads failed
+	PartialFailure = 1,
+	/// Nothing could be downloaded
+	TotalFailure = 2,
+	/// Settings missing or invalid
+	ConfigError = 3,
+	/// Login failed
+	AuthError = 4,
+	/// Input is no valid or available URI, or the report to retry can't be read
+	InvalidInput = 5,
+}
+
+impl Exit {
+	/// From the outcome of the downloads
+	fn from_report(report: &Report) -> Exit {
+		Exit::from_counts(report.done + report.skipped, report.failed)
+	}
+
+	/// From the number of items which succeeded and failed
+	fn from_counts(succeeded: usize, failed: usize) -> Exit {
+		match (failed, succeeded) {
+			(0, _) => Exit::Success,
+			(_, 0) => Exit::TotalFailure,
+			_ => Exit::PartialFailure,
+		}
+	}
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
+	/// From the error of adding the input to the queue
+	fn from_input_error(e: &SpotifyError) -> Exit {
+		match e {
+			SpotifyError::InvalidUri
+			| SpotifyError::Unavailable
+			| SpotifyError::ParseError(_)
+			| SpotifyError::SpotifyIdError => Exit::InvalidInput,
+			_ => Exit::TotalFailure,
+		}
+	}
+}
+
+impl From<Exit> for ExitCode {
+	fn from(exit: Exit) -> Self {
+		ExitCode::from(exit as u8)
+	}
+}
 
 #[cfg(not(windows))]
 #[tokio::main]
-async fn main() {
-	start().await;
+async fn main() -> ExitCode {
+	start().await.into()
 }
 
 #[cfg(windows)]
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
 	use colored::control;
 
 	//backwards compatibility.
 	if control::set_virtual_terminal(true).is_ok() {};
-	start().await;
+	start().await.into()
 }
 
-async fn start() {
+async fn start() -> Exit {
 	env_logger::init();
 
 	let args = Args::from_cli();
+	// Redrawing only makes sense in a terminal
+	let format = match args.output {
+		Some(output) => output,
+		None if args.quiet || !std::io::stdout().is_terminal() => OutputFormat::Plain,
+		None => OutputFormat::Human,
+	};
 
 	let settings = match Settings::load().await {
 		Ok(settings) => {
-			println!(
-				"{} {}.",
-				"Settings successfully loaded.\nContinuing with spotify account:".green(),
-				settings.username
-			);
+			if !args.quiet {
+				eprintln!(
+					"{} {}.",
+					"Settings successfully loaded.\nContinuing with spotify account:".green(),
+					settings.username
+				);
+			}
 			settings
 		}
 		Err(e) => {
-			println!(
+			eprintln!(
 				"{} {}...",
 				"Settings could not be loaded, because of the following error:".red(),
 				e
@@ -60,24 +119,38 @@ async fn start() {
 			let default_settings = Settings::new("username", "password", "client_id", "secret");
 			match default_settings.save().await {
 				Ok(path) => {
-					println!(
+					eprintln!(
                         "{}{}",
                         "..but default settings have been created successfully. Edit them and run the program again.\nFind the settings file at: ".green(),
                         path.to_string_lossy()
                     );
 				}
 				Err(e) => {
-					println!(
+					eprintln!(
 						"{} {}",
 						"..and default settings could not be written:".red(),
 						e
 					);
 				}
 			};
-			return;
+			return Exit::ConfigError;
 		}
 	};
 
+	if let Err(e) = settings.downloader.validate() {
+		eprintln!("{} {}", "Invalid settings:".red(), e);
+		return Exit::ConfigError;
+	}
+
+	if let Some(Commands::Reorganize {
+		path,
+		dry_run,
+		undo,
+	}) = args.command
+	{
+		return reorganize(settings.downloader, path, dry_run, undo).await;
+	}
+
 	let spotify = match Spotify::new(
 		&settings.username,
 		&settings.password,
@@ -88,33 +161,47 @@ async fn start() {
 	.await
 	{
 		Ok(spotify) => {
-			println!("{}", "Login succeeded.".green());
+			if !args.quiet {
+				eprintln!("{}", "Login succeeded.".green());
+			}
 			spotify
 		}
 		Err(e) => {
-			println!(
+			eprintln!(
 				"{} {}",
 				"Login failed, possibly due to invalid credentials or settings:".red(),
 				e
 			);
-			return;
+			return Exit::AuthError;
 		}
 	};
 
-	let downloader = Downloader::new(settings.downloader, spotify);
+	if let Some(Commands::Retag { path, dry_run }) = &args.command {
+		return retag(spotify, settings.downloader, path, *dry_run).await;
+	}
+	let input = match &args.retry_failed {
+		Some(path) => format!("--retry-failed {}", path.to_string_lossy()),
+		None => args.input.unwrap_or_default(),
+	};
 
-	let bold = "\x1b[1m";
-	let bold_off = "\x1b[0m";
+	let downloader = Downloader::new(settings.downloader, spotify);
+	let events = downloader.subscribe();
 
-	match downloader.handle_input(&args.input).await {
+	let result = match &args.retry_failed {
+		Some(path) => retry_failed(&downloader, path).await.map(|_| None),
+		None => downloader.handle_input(&input).await,
+	};
+	match result {
 		Ok(search_results) => {
 			if let Some(search_results) = search_results {
-				print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
+				if format == OutputFormat::Human {
+					print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
+				}
 
 				for (i, track) in search_results.iter().enumerate() {
-					println!("{}: {} - {}", i + 1, track.author, track.title);
+					eprintln!("{}: {} - {}", i + 1, track.author, track.title);
 				}
-				println!("{}", "Select the track (default: 1): ".green());
+				eprintln!("{}", "Select the track (default: 1): ".green());
 
 				let mut selection;
 				loop {
@@ -128,7 +215,7 @@ async fn start() {
 					if selection < search_results.len() {
 						break;
 					}
-					println!("{}", "Invalid selection. Try again or quit (CTRL+C):".red());
+					eprintln!("{}", "Invalid selection. Try again or quit (CTRL+C):".red());
 				}
 
 				let track = &search_results[selection];
@@ -145,196 +232,252 @@ async fn start() {
 							e
 						)
 					);
-					return;
+					return Exit::from_input_error(&e);
 				}
 			}
 
-			let refresh = Duration::from_secs(settings.refresh_ui_seconds);
-			let now = Instant::now();
-			let mut time_elapsed: u64 = 0;
-
-			let mut download_states =
-				vec![DownloadState::None; downloader.get_downloads().await.len()];
-			let mut messages = vec![];
-			let mut errors = vec![];
-
-			'outer: loop {
-				print!("\x1b[2J\x1b[1;1H");
-				let mut exit_flag: i8 = 1;
-
-				let mut num_completed = 0;
-				let mut num_err = 0;
-				let mut num_skipped = 0;
-				let mut num_downloading = 0;
-				let mut num_waiting = 0;
-
-				let mut current_download_view = String::new();
-
-				let mut progress_sum = 0.;
-
-				for (i, download) in downloader.get_downloads().await.iter().enumerate() {
-					let state = &download.state;
-
-					if state != &download_states[i] {
-						download_states[i] = state.clone();
-						match state {
-							DownloadState::None => (),
-							DownloadState::Lock => (),
-							DownloadState::Downloading(_, _) => (),
-							DownloadState::Post => (),
-							DownloadState::Done => messages.push(format!(
-								" {} | {}: {}",
-								secs_to_hrs_min_sec(time_elapsed as i32),
-								"Downloaded".green(),
-								download.title
-							)),
-							DownloadState::Error(e) => {
-								let msg = format!(
-									" {} | {}: {}",
-									secs_to_hrs_min_sec(time_elapsed as i32),
-									if e == &SpotifyError::AlreadyDownloaded {
-										e.to_string().yellow()
-									} else {
-										e.to_string().red()
-									},
-									download.title
-								);
-								if e == &SpotifyError::AlreadyDownloaded {
-									messages.push(msg);
-								} else {
-									errors.push(msg);
-								}
-							}
-						};
-					}
-
-					if let Some(msg) = match state {
-						DownloadState::Downloading(r, t) => {
-							exit_flag &= 0;
-							let p = *r as f32 / *t as f32;
-							progress_sum += p;
-							num_downloading += 1;
-							if p > 1. {
-								Some("100%".to_string())
-							} else {
-								Some(format!("{}%", (p * 100.) as i8))
-							}
-						}
-						DownloadState::Post => {
-							exit_flag &= 0;
-							Some("Postprocessing... ".to_string())
-						}
-						DownloadState::None | DownloadState::Lock => {
-							exit_flag &= 0;
-							num_waiting += 1;
-							None
-						}
-						DownloadState::Error(e) => {
-							if e == &SpotifyError::AlreadyDownloaded {
-								num_skipped += 1;
-							} else {
-								num_err += 1;
-							}
-
-							None
-						}
-						DownloadState::Done => {
-							num_completed += 1;
-							None
+			match format {
+				OutputFormat::Json => {
+					output::json(&downloader, events, args.quiet).await;
+				}
+				OutputFormat::Plain => {
+					output::plain(&downloader, events, args.quiet).await;
+				}
+				// Quiet dashboard is the plain output
+				OutputFormat::Human if args.quiet => {
+					output::plain(&downloader, events, true).await;
+				}
+				OutputFormat::Human => {
+					let refresh = Duration::from_secs(settings.refresh_ui_seconds);
+					match tui::run(&downloader, events, refresh).await {
+						Ok(summary) => {
+							println!("{}", summary);
+							output::print_stats(&downloader.get_stats().await);
 						}
-					} {
-						current_download_view
-							.push_str(&format!("{: >4} | {}\n", msg, download.title));
+						Err(e) => error!("{} {}", "Terminal UI failed:".red(), e),
 					}
 				}
+			}
 
-				while messages.len() > 10 {
-					messages.remove(0);
-				}
+			let report_path = args.report.unwrap_or_else(settings::get_last_run_path);
+			Exit::from_report(&write_report(&downloader, &input, &report_path).await)
+		}
+		Err(e) => {
+			error!("{} {}", "Handling input failed:".red(), e);
+			match args.retry_failed {
+				Some(_) => Exit::InvalidInput,
+				None => Exit::from_input_error(&e),
+			}
+		}
+	}
+}
 
-				println!(" {bold}\x1b[0;34m- DownOnSpot v{VERSION} -\x1b[0m{bold_off}\n");
+/// Queue the failed tracks of a report, fails only if the report can't be read
+async fn retry_failed(downloader: &Downloader, path: &Path) -> Result<(), SpotifyError> {
+	let report = Report::load(path).await?;
+	for failure in &report.failures {
+		if let Err(e) = downloader
+			.add_uri_from(&failure.uri, failure.source.as_deref())
+			.await
+		{
+			error!("{} {}: {}", "Failed queueing".red(), failure.uri, e);
+		}
+	}
+	Ok(())
+}
 
-				println!(
-					"Time elapsed:   {}",
-					secs_to_hrs_min_sec(time_elapsed as i32)
-				);
-				println!(
-					"Time remaining: {}\n",
-					secs_to_hrs_min_sec(
-						(time_elapsed as f32
-							/ (progress_sum + num_completed as f32 + num_err as f32)
-							* (num_waiting as f32 + num_downloading as f32 - progress_sum))
-							.round() as i32
-					)
-				);
+/// Write report of the finished run, failed tracks can be retried with `--retry-failed`
+async fn write_report(downloader: &Downloader, input: &str, path: &Path) -> Report {
+	let report = Report::new(input, &downloader.get_downloads().await);
+	if let Err(e) = report.save(path).await {
+		error!("{} {}", "Failed writing report:".red(), e);
+		return report;
+	}
+	if report.failed > 0 {
+		eprintln!(
+			"{} track(s) failed, list written to {}. Retry them with: --retry-failed {}",
+			report.failed,
+			Report::failed_list_path(path).to_string_lossy(),
+			path.to_string_lossy()
+		);
+	}
+	report
+}
 
-				println!(
-					" {bold}  {}      {}{bold_off}",
-					"Time".underline(),
-					"Event".underline()
-				);
-				for message in messages.iter().rev() {
-					println!("{}", message);
-				}
+/// Retag all files in directory and print the changes
+async fn retag(spotify: Spotify, config: DownloaderConfig, path: &Path, dry_run: bool) -> Exit {
+	let files = match Retagger::find_files(path) {
+		Ok(files) => files,
+		Err(e) => {
+			println!("{} {}", "Finding files failed:".red(), e);
+			return Exit::InvalidInput;
+		}
+	};
 
-				if !errors.is_empty() {
+	let retagger = Retagger::new(Arc::new(spotify), config);
+	let mut num_changed = 0;
+	let mut num_err = 0;
+	for file in &files {
+		match retagger.retag(file, dry_run).await {
+			Ok(changes) => {
+				if changes.is_empty() {
+					continue;
+				}
+				num_changed += 1;
+				println!("{}", file.to_string_lossy().bold());
+				for change in changes {
 					println!(
-						"\n {bold}  {}      {}{bold_off}",
-						"Time".underline(),
-						"Error".underline()
+						"  {} {}: {}",
+						"-".red(),
+						change.name,
+						change.old.join("; ").red()
+					);
+					println!(
+						"  {} {}: {}",
+						"+".green(),
+						change.name,
+						change.new.join("; ").green()
 					);
-					for error in errors.iter().rev().take(5) {
-						println!("{}", error);
-					}
 				}
-
-				println!("\n\n {}", "Current downloads:".underline().bold());
-				println!("{}", current_download_view);
-
-				println!(
-					"\n{bold}{}|{}|{}|{}| Total{bold_off}",
-					" Waiting ",
-					" Failed  ".red(),
-					" Skipped ".yellow(),
-					" Done    ".green()
-				);
+			}
+			Err(e) => {
+				num_err += 1;
 				println!(
-					" {: <8}| {: <8}| {: <8}| {: <8}| {}",
-					num_waiting,
-					num_err,
-					num_skipped,
-					num_completed,
-					download_states.len()
+					"{} {}: {}",
+					"Retagging failed:".red(),
+					file.to_string_lossy(),
+					e
 				);
+			}
+		}
+	}
 
-				time_elapsed = now.elapsed().as_secs();
-				if exit_flag == 1 {
-					break 'outer;
-				}
+	println!(
+		"\n{} file(s) {}, {} unchanged, {} failed.",
+		num_changed,
+		match dry_run {
+			true => "would change",
+			false => "changed",
+		},
+		files.len() - num_changed - num_err,
+		num_err
+	);
+	Exit::from_counts(files.len() - num_err, num_err)
+}
 
-				task::sleep(refresh).await
+/// Move files into the current template layout, or undo a previous reorganization
+async fn reorganize(
+	config: DownloaderConfig,
+	path: Option<PathBuf>,
+	dry_run: bool,
+	undo: Option<PathBuf>,
+) -> Exit {
+	// Undo
+	if let Some(log_path) = undo {
+		return match task::spawn_blocking(move || Reorganizer::undo(log_path)).await {
+			Ok(log) => {
+				println!(
+					"{} {} file(s) moved back, {} playlist(s) restored.",
+					"Undo succeeded.".green(),
+					log.moves.len(),
+					log.playlists.len()
+				);
+				Exit::Success
+			}
+			Err(e) => {
+				println!("{} {}", "Undo failed:".red(), e);
+				Exit::TotalFailure
 			}
+		};
+	}
 
+	let path = path.unwrap_or_default();
+	let log_path = path.join(format!(
+		"reorganize-undo-{}.json",
+		chrono::Local::now().format("%Y%m%d-%H%M%S")
+	));
+	let log_path_clone = log_path.clone();
+	// Files which can't be moved are skipped by the plan
+	let result = task::spawn_blocking(move || {
+		let reorganizer = Reorganizer::new(config);
+		let plan = reorganizer.plan(&path)?;
+		print_plan(&plan);
+		let skipped = plan.collisions.len() + plan.errors.len();
+		if dry_run || plan.moves.is_empty() {
+			return Ok((plan.moves.len(), skipped, None));
+		}
+		let log = reorganizer.execute(&plan, log_path_clone)?;
+		Ok::<_, SpotifyError>((plan.moves.len(), skipped, Some(log)))
+	})
+	.await;
+
+	match result {
+		Ok((_, skipped, Some(log))) => {
 			println!(
-				"Finished download(s) in {}.",
-				secs_to_hrs_min_sec(time_elapsed as i32)
+				"{} {} file(s) moved, {} playlist(s) updated.\nUndo log: {}",
+				"Reorganization succeeded.".green(),
+				log.moves.len(),
+				log.playlists.len(),
+				log_path.to_string_lossy()
 			);
-
-			if !errors.is_empty() {
-				println!("\n\n All Errors:");
-				for error in errors {
-					println!("{}", error);
-				}
-			}
+			Exit::from_counts(log.moves.len(), skipped)
 		}
+		Ok((moves, skipped, None)) => Exit::from_counts(moves, skipped),
 		Err(e) => {
-			error!("{} {}", "Handling input failed:".red(), e)
+			println!(
+				"{} {}\nUndo log, if any moves were made: {}",
+				"Reorganization failed:".red(),
+				e,
+				log_path.to_string_lossy()
+			);
+			// Moves made before the error are in the undo log
+			let moved = tokio::fs::read_to_string(&log_path)
+				.await
+				.ok()
+				.and_then(|data| serde_json::from_str::<UndoLog>(&data).ok())
+				.map(|log| log.moves.len())
+				.unwrap_or(0);
+			Exit::from_counts(moved, 1)
 		}
 	}
 }
 
-fn secs_to_hrs_min_sec(secs: i32) -> String {
-	format!("{:0>2}:{:0>2}:{:0>2}", secs / 3600, (secs % 3600) / 60, secs % 60)
+/// Print planned moves, collisions and errors
+fn print_plan(plan: &Plan) {
+	for m in &plan.moves {
+		println!(
+			"{}\n  {} {}",
+			m.from.to_string_lossy(),
+			"->".green(),
+			m.to.to_string_lossy()
+		);
+	}
+	for playlist in &plan.playlists {
+		println!(
+			"{} {}",
+			"Update playlist:".green(),
+			playlist.path.to_string_lossy()
+		);
+	}
+	for collision in &plan.collisions {
+		println!(
+			"{} {}",
+			"Collision, skipping:".red(),
+			collision.target.to_string_lossy()
+		);
+		for source in &collision.sources {
+			println!("  {} {}", "<-".red(), source.to_string_lossy());
+		}
+	}
+	for (path, e) in &plan.errors {
+		println!("{} {}: {}", "Skipping:".yellow(), path.to_string_lossy(), e);
+	}
+	println!(
+		"\n{} move(s), {} collision(s), {} error(s).",
+		plan.moves.len(),
+		plan.collisions.len(),
+		plan.errors.len()
+	);
 }
 
 // !cargo b --release
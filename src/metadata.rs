@@ -0,0 +1,77 @@
+use aspotify::{Album, Track, TrackSimplified};
+use async_trait::async_trait;
+
+use crate::error::SpotifyError;
+use crate::spotify::{Spotify, SpotifyItem};
+
+/// Source of track, album and playlist metadata
+#[async_trait]
+pub trait MetadataProvider: Send + Sync {
+	/// Fetch data for URI
+	async fn resolve_uri(&self, uri: &str) -> Result<SpotifyItem, SpotifyError>;
+
+	/// Get search results for query
+	async fn search(&self, query: &str) -> Result<Vec<Track>, SpotifyError>;
+
+	/// Get all tracks from playlist
+	async fn full_playlist(&self, id: &str) -> Result<Vec<Track>, SpotifyError>;
+
+	/// Get all tracks from album
+	async fn full_album(&self, id: &str) -> Result<Vec<TrackSimplified>, SpotifyError>;
+
+	/// Get all tracks from artist, with the ID of their album
+	async fn full_artist(&self, id: &str) -> Result<Vec<(String, TrackSimplified)>, SpotifyError>;
+
+	/// Get full track
+	async fn get_track(&self, id: &str) -> Result<Track, SpotifyError>;
+
+	/// Get full album
+	async fn get_album(&self, id: &str) -> Result<Album, SpotifyError>;
+
+	/// Fetch metadata of tracks ahead of `get_track` and `get_album`
+	async fn prefetch(&self, _track_ids: &[String]) -> Result<(), SpotifyError> {
+		Ok(())
+	}
+
+	/// Keep track which was already fetched for `get_track`
+	async fn cache_track(&self, _track: Track) {}
+}
+
+#[async_trait]
+impl MetadataProvider for Spotify {
+	async fn resolve_uri(&self, uri: &str) -> Result<SpotifyItem, SpotifyError> {
+		Spotify::resolve_uri(self, uri).await
+	}
+
+	async fn search(&self, query: &str) -> Result<Vec<Track>, SpotifyError> {
+		Spotify::search(self, query).await
+	}
+
+	async fn full_playlist(&self, id: &str) -> Result<Vec<Track>, SpotifyError> {
+		Spotify::full_playlist(self, id).await
+	}
+
+	async fn full_album(&self, id: &str) -> Result<Vec<TrackSimplified>, SpotifyError> {
+		Spotify::full_album(self, id).await
+	}
+
+	async fn full_artist(&self, id: &str) -> Result<Vec<(String, TrackSimplified)>, SpotifyError> {
+		Spotify::full_artist(self, id).await
+	}
+
+	async fn get_track(&self, id: &str) -> Result<Track, SpotifyError> {
+		Spotify::get_track(self, id).await
+	}
+
+	async fn get_album(&self, id: &str) -> Result<Album, SpotifyError> {
+		Spotify::get_album(self, id).await
+	}
+
+	async fn prefetch(&self, track_ids: &[String]) -> Result<(), SpotifyError> {
+		Spotify::prefetch(self, track_ids).await
+	}
+
+	async fn cache_track(&self, track: Track) {
+		Spotify::cache_track(self, track).await
+	}
+}
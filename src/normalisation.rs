@@ -0,0 +1,234 @@
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+use crate::error::SpotifyError;
+use crate::replaygain::{format_gain, format_peak, REFERENCE_LOUDNESS};
+
+/// Size of the header Spotify prepends to Ogg files
+pub const SPOTIFY_OGG_HEADER_SIZE: usize = 0xa7;
+/// Offset of the normalisation data in the header
+const NORMALISATION_OFFSET: usize = 144;
+/// Loudness Spotify's gains are relative to, in LUFS
+pub const SPOTIFY_REFERENCE_LOUDNESS: f64 = -14.0;
+/// Level the limiter keeps the signal under, -1 dBFS
+const LIMITER_THRESHOLD: f32 = 0.891;
+/// Time the limiter takes to recover by 1/e, in seconds
+const LIMITER_RELEASE: f32 = 0.1;
+
+/// Which gain of the normalisation data is applied
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum NormalisationMode {
+	#[default]
+	Off,
+	Track,
+	Album,
+}
+
+/// Volume normalisation applied when converting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NormalisationConfig {
+	pub mode: NormalisationMode,
+	/// Target loudness in LUFS
+	pub target: f64,
+	/// Limit peaks above -1 dBFS, otherwise the gain is lowered so the peak doesn't clip
+	pub limiter: bool,
+}
+
+impl Default for NormalisationConfig {
+	fn default() -> Self {
+		NormalisationConfig {
+			mode: NormalisationMode::Off,
+			target: SPOTIFY_REFERENCE_LOUDNESS,
+			limiter: true,
+		}
+	}
+}
+
+impl NormalisationConfig {
+	/// Check for invalid values
+	pub fn validate(&self) -> Result<(), SpotifyError> {
+		if !(-70.0..=0.0).contains(&self.target) {
+			return Err(SpotifyError::InvalidConfig(format!(
+				"normalisation.target must be between -70 and 0 LUFS, got {}",
+				self.target
+			)));
+		}
+		Ok(())
+	}
+
+	/// Gain to apply to track in dB, `None` if disabled
+	pub fn gain_db(&self, data: &NormalisationData) -> Option<f32> {
+		let (gain, peak) = match self.mode {
+			NormalisationMode::Off => return None,
+			NormalisationMode::Track => (data.track_gain_db, data.track_peak),
+			NormalisationMode::Album => (data.album_gain_db, data.album_peak),
+		};
+		let mut gain = gain + (self.target - SPOTIFY_REFERENCE_LOUDNESS) as f32;
+		if !self.limiter && peak > 0.0 {
+			gain = gain.min(-20.0 * peak.log10());
+		}
+		Some(gain)
+	}
+}
+
+/// Loudness data of Spotify's Ogg header
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormalisationData {
+	/// Gain to Spotify's reference loudness in dB
+	pub track_gain_db: f32,
+	/// Linear peak amplitude
+	pub track_peak: f32,
+	pub album_gain_db: f32,
+	pub album_peak: f32,
+}
+
+impl NormalisationData {
+	/// Parse Spotify's Ogg header
+	pub fn parse(header: &[u8]) -> Result<NormalisationData, SpotifyError> {
+		if header.len() < SPOTIFY_OGG_HEADER_SIZE || &header[0..4] != b"OggS" {
+			return Err(SpotifyError::DecodeError(
+				"Invalid Spotify Ogg header".to_string(),
+			));
+		}
+		let value = |i: usize| {
+			let offset = NORMALISATION_OFFSET + i * 4;
+			f32::from_le_bytes([
+				header[offset],
+				header[offset + 1],
+				header[offset + 2],
+				header[offset + 3],
+			])
+		};
+		let data = NormalisationData {
+			track_gain_db: value(0),
+			track_peak: value(1),
+			album_gain_db: value(2),
+			album_peak: value(3),
+		};
+		if [
+			data.track_gain_db,
+			data.track_peak,
+			data.album_gain_db,
+			data.album_peak,
+		]
+		.iter()
+		.any(|v| !v.is_finite())
+		{
+			return Err(SpotifyError::DecodeError(
+				"Invalid Spotify normalisation data".to_string(),
+			));
+		}
+		Ok(data)
+	}
+
+	/// Read the header from start of decrypted stream, leaving the stream at the Ogg data ( BLOCKING )
+	///
+	/// Invalid loudness data isn't fatal, the track is downloaded without normalisation.
+	pub fn read(read: &mut impl Read) -> Result<Option<NormalisationData>, SpotifyError> {
+		let mut header = [0; SPOTIFY_OGG_HEADER_SIZE];
+		read.read_exact(&mut header)?;
+		match NormalisationData::parse(&header) {
+			Ok(data) => Ok(Some(data)),
+			Err(e) => {
+				warn!("Ignoring normalisation data! {}", e);
+				Ok(None)
+			}
+		}
+	}
+
+	/// Normalisation data after applying gain
+	pub fn with_gain(&self, gain_db: f32) -> NormalisationData {
+		let factor = db_to_ratio(gain_db);
+		NormalisationData {
+			track_gain_db: self.track_gain_db - gain_db,
+			track_peak: (self.track_peak * factor).min(1.0),
+			album_gain_db: self.album_gain_db - gain_db,
+			album_peak: (self.album_peak * factor).min(1.0),
+		}
+	}
+
+	/// ReplayGain 2.0 tags, gains converted to its reference loudness
+	pub fn replaygain_tags(&self) -> Vec<(&'static str, String)> {
+		let offset = REFERENCE_LOUDNESS - SPOTIFY_REFERENCE_LOUDNESS;
+		vec![
+			(
+				"REPLAYGAIN_TRACK_GAIN",
+				format_gain(self.track_gain_db as f64 + offset),
+			),
+			("REPLAYGAIN_TRACK_PEAK", format_peak(self.track_peak)),
+			(
+				"REPLAYGAIN_ALBUM_GAIN",
+				format_gain(self.album_gain_db as f64 + offset),
+			),
+			("REPLAYGAIN_ALBUM_PEAK", format_peak(self.album_peak)),
+		]
+	}
+}
+
+/// Scales planar samples by a fixed gain
+pub struct Normaliser {
+	factor: f32,
+	limiter: Option<Limiter>,
+}
+
+impl Normaliser {
+	/// Create new instance
+	pub fn new(gain_db: f32, limiter: bool, sample_rate: u32) -> Normaliser {
+		Normaliser {
+			factor: db_to_ratio(gain_db),
+			limiter: match limiter {
+				true => Some(Limiter::new(sample_rate)),
+				false => None,
+			},
+		}
+	}
+
+	/// Apply gain to packet
+	pub fn process(&mut self, packet: &mut [Vec<i16>]) {
+		let length = packet.iter().map(|c| c.len()).min().unwrap_or(0);
+		for i in 0..length {
+			// Channels are limited together to keep the stereo image
+			let peak = packet
+				.iter()
+				.map(|c| (c[i] as f32 * self.factor / 32768.0).abs())
+				.fold(0.0, f32::max);
+			let gain = match &mut self.limiter {
+				Some(limiter) => limiter.gain(peak),
+				None => 1.0,
+			};
+			for channel in packet.iter_mut() {
+				let sample = channel[i] as f32 * self.factor * gain;
+				channel[i] = sample.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+			}
+		}
+	}
+}
+
+/// Peak limiter with instant attack and exponential release
+struct Limiter {
+	release: f32,
+	envelope: f32,
+}
+
+impl Limiter {
+	fn new(sample_rate: u32) -> Limiter {
+		Limiter {
+			release: (-1.0 / (LIMITER_RELEASE * sample_rate.max(1) as f32)).exp(),
+			envelope: 0.0,
+		}
+	}
+
+	/// Gain reduction for sample with peak
+	fn gain(&mut self, peak: f32) -> f32 {
+		self.envelope = peak.max(self.envelope * self.release);
+		match self.envelope > LIMITER_THRESHOLD {
+			true => LIMITER_THRESHOLD / self.envelope,
+			false => 1.0,
+		}
+	}
+}
+
+fn db_to_ratio(db: f32) -> f32 {
+	10f32.powf(db / 20.0)
+}
@@ -0,0 +1,304 @@
+use down_on_spot::{
+	Download, DownloadEvent, DownloadState, DownloadStats, Downloader, SpotifyError,
+};
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Instant;
+
+/// State change of a download, a single line of JSON output
+#[derive(Debug, Serialize)]
+struct StateLine<'a> {
+	event: &'static str,
+	id: i64,
+	track_id: &'a str,
+	title: &'a str,
+	state: &'static str,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	read: Option<usize>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	total: Option<usize>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	path: Option<&'a Path>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	error: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	error_kind: Option<&'static str>,
+}
+
+impl<'a> From<&'a Download> for StateLine<'a> {
+	fn from(download: &'a Download) -> Self {
+		let (read, total) = match download.state {
+			DownloadState::Downloading(read, total) => (Some(read), Some(total)),
+			_ => (None, None),
+		};
+		let error = match &download.state {
+			DownloadState::Error(e) => Some(e),
+			_ => None,
+		};
+		StateLine {
+			event: "state",
+			id: download.id,
+			track_id: &download.track_id,
+			title: &download.title,
+			state: state_name(&download.state),
+			read,
+			total,
+			path: download.path.as_deref(),
+			error: error.map(|e| e.to_string()),
+			error_kind: error.map(|e| e.kind()),
+		}
+	}
+}
+
+/// Counts of finished downloads, the last line of JSON output
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+	event: &'static str,
+	pub total: usize,
+	pub done: usize,
+	pub skipped: usize,
+	pub failed: usize,
+	pub elapsed_secs: u64,
+	/// Bytes received
+	pub bytes: u64,
+	/// Bytes per second while downloading
+	pub average_speed: f64,
+	/// Average time of a track per stage
+	pub waiting_secs: f64,
+	pub downloading_secs: f64,
+	pub post_processing_secs: f64,
+}
+
+impl Summary {
+	/// Count downloads by state
+	pub fn new(downloads: &[Download], start: Instant) -> Summary {
+		let mut summary = Summary {
+			event: "summary",
+			total: downloads.len(),
+			elapsed_secs: start.elapsed().as_secs(),
+			..Default::default()
+		};
+		for download in downloads {
+			match &download.state {
+				DownloadState::Done => summary.done += 1,
+				DownloadState::Error(SpotifyError::AlreadyDownloaded) => summary.skipped += 1,
+				// Unfinished ones were left when quitting early
+				_ => summary.failed += 1,
+			}
+		}
+		summary
+	}
+
+	/// Add throughput and time per stage
+	pub fn with_stats(self, stats: &DownloadStats) -> Summary {
+		Summary {
+			bytes: stats.bytes,
+			average_speed: stats.average_speed(),
+			waiting_secs: stats.waiting.average().as_secs_f64(),
+			downloading_secs: stats.downloading.average().as_secs_f64(),
+			post_processing_secs: stats.post_processing.average().as_secs_f64(),
+			..self
+		}
+	}
+}
+
+impl fmt::Display for Summary {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"Finished {} download(s) in {}s: {} done, {} skipped, {} failed.",
+			self.total, self.elapsed_secs, self.done, self.skipped, self.failed
+		)
+	}
+}
+
+/// Print every state change as NDJSON until all downloads are finished
+///
+/// `events` has to be subscribed before anything was added to the queue.
+pub async fn json(
+	downloader: &Downloader,
+	events: BoxStream<'static, DownloadEvent>,
+	quiet: bool,
+) -> Summary {
+	let summary = follow(downloader, events, |download| {
+		if !quiet || is_failed(download) {
+			print_line(&StateLine::from(download));
+		}
+	})
+	.await
+	.with_stats(&downloader.get_stats().await);
+	print_line(&summary);
+	summary
+}
+
+/// Print a line with time, state and title whenever the state of a download changes
+///
+/// `events` has to be subscribed before anything was added to the queue.
+pub async fn plain(
+	downloader: &Downloader,
+	events: BoxStream<'static, DownloadEvent>,
+	quiet: bool,
+) -> Summary {
+	// Progress doesn't get a line of its own
+	let mut printed: HashMap<i64, &'static str> = HashMap::new();
+	let summary = follow(downloader, events, |download| {
+		let state = state_name(&download.state);
+		if printed.insert(download.id, state) == Some(state) || (quiet && !is_failed(download)) {
+			return;
+		}
+		let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+		match (&download.state, &download.path) {
+			(DownloadState::Error(e), _) => {
+				println!("{} {: <15} {}: {}", time, state, download.title, e)
+			}
+			(DownloadState::Done, Some(path)) => println!(
+				"{} {: <15} {} ({})",
+				time,
+				state,
+				download.title,
+				path.to_string_lossy()
+			),
+			_ => println!("{} {: <15} {}", time, state, download.title),
+		}
+	})
+	.await;
+	println!("{}", summary);
+	print_stats(&downloader.get_stats().await);
+	summary
+}
+
+/// Follow the events until all downloads are finished, calling `on_change` on every state change
+async fn follow(
+	downloader: &Downloader,
+	mut events: BoxStream<'static, DownloadEvent>,
+	mut on_change: impl FnMut(&Download),
+) -> Summary {
+	let start = Instant::now();
+	// All queued downloads have been announced once this returns
+	let queued = downloader.get_downloads().await.len();
+	let mut downloads: Vec<Download> = vec![];
+
+	while downloads.len() < queued || !downloads.iter().all(is_finished) {
+		let event = match events.next().await {
+			Some(event) => event,
+			None => break,
+		};
+		if let DownloadEvent::Lagged(_) = event {
+			let current = downloader.get_downloads().await;
+			for download in &current {
+				let changed = downloads
+					.iter()
+					.find(|d| d.id == download.id)
+					.map(|d| d.state != download.state)
+					.unwrap_or(true);
+				if changed {
+					on_change(download);
+				}
+			}
+			downloads = current;
+			continue;
+		}
+		if let Some(download) = apply_event(&mut downloads, event) {
+			on_change(download);
+		}
+	}
+
+	Summary::new(&downloads, start)
+}
+
+/// Update the download the event belongs to, returns it if its state changed
+pub fn apply_event(downloads: &mut Vec<Download>, event: DownloadEvent) -> Option<&Download> {
+	if let DownloadEvent::Queued(download) = event {
+		// Already known from a snapshot
+		if downloads.iter().any(|d| d.id == download.id) {
+			return None;
+		}
+		downloads.push(download);
+		return downloads.last();
+	}
+	let download = downloads.iter_mut().find(|d| Some(d.id) == event.id())?;
+	if let DownloadEvent::Finished(_, path) = &event {
+		download.path = Some(path.clone());
+	}
+	let state = event.state()?;
+	if download.state == state {
+		return None;
+	}
+	download.state = state;
+	Some(&*download)
+}
+
+/// Whether the download won't change anymore
+pub fn is_finished(download: &Download) -> bool {
+	matches!(
+		download.state,
+		DownloadState::Done | DownloadState::Error(_)
+	)
+}
+
+/// Whether the download failed, skipped ones didn't
+pub fn is_failed(download: &Download) -> bool {
+	match &download.state {
+		DownloadState::Error(e) => e != &SpotifyError::AlreadyDownloaded,
+		_ => false,
+	}
+}
+
+/// Name of the state in the output
+pub fn state_name(state: &DownloadState) -> &'static str {
+	match state {
+		DownloadState::None => "queued",
+		DownloadState::Lock => "locked",
+		DownloadState::Downloading(_, _) => "downloading",
+		DownloadState::Post => "post_processing",
+		DownloadState::Done => "done",
+		DownloadState::Error(SpotifyError::AlreadyDownloaded) => "skipped",
+		DownloadState::Error(SpotifyError::Cancelled) => "cancelled",
+		DownloadState::Error(_) => "failed",
+	}
+}
+
+/// Print throughput and average time per stage
+pub fn print_stats(stats: &DownloadStats) {
+	println!(
+		"Downloaded {} at {}/s on average.",
+		format_bytes(stats.bytes as f64),
+		format_bytes(stats.average_speed())
+	);
+	println!(
+		"Average per track: {:.1}s queued, {:.1}s downloading, {:.1}s post processing.",
+		stats.waiting.average().as_secs_f64(),
+		stats.downloading.average().as_secs_f64(),
+		stats.post_processing.average().as_secs_f64()
+	);
+}
+
+/// Print value as a single line of JSON
+fn print_line(value: &impl Serialize) {
+	match serde_json::to_string(value) {
+		Ok(line) => println!("{}", line),
+		Err(e) => error!("Failed serializing output! {}", e),
+	}
+}
+
+/// Human readable amount of bytes
+pub fn format_bytes(bytes: f64) -> String {
+	match bytes {
+		b if b >= 1024. * 1024. => format!("{:.1} MB", b / 1024. / 1024.),
+		b if b >= 1024. => format!("{:.1} KB", b / 1024.),
+		b => format!("{:.0} B", b),
+	}
+}
+
+pub fn secs_to_hrs_min_sec(secs: u64) -> String {
+	format!(
+		"{:0>2}:{:0>2}:{:0>2}",
+		secs / 3600,
+		(secs % 3600) / 60,
+		secs % 60
+	)
+}
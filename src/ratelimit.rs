@@ -0,0 +1,127 @@
+use async_std::sync::Mutex;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+use crate::error::SpotifyError;
+
+/// Web API requests running at once
+pub const MAX_CONCURRENT_REQUESTS: usize = 4;
+/// Retries of a rate limited request before giving up
+pub const MAX_RETRIES: usize = 5;
+/// Wait if the response has no Retry-After, doubled on every retry
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(2);
+
+/// Caps concurrent requests and pauses all of them while rate limited
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+	permits: Arc<Semaphore>,
+	blocked_until: Arc<Mutex<Option<Instant>>>,
+	max_retries: usize,
+}
+
+impl Default for RateLimiter {
+	fn default() -> Self {
+		RateLimiter::new(MAX_CONCURRENT_REQUESTS, MAX_RETRIES)
+	}
+}
+
+impl RateLimiter {
+	/// Create new instance
+	pub fn new(concurrency: usize, max_retries: usize) -> RateLimiter {
+		RateLimiter {
+			permits: Arc::new(Semaphore::new(concurrency.max(1))),
+			blocked_until: Arc::new(Mutex::new(None)),
+			max_retries,
+		}
+	}
+
+	/// Run request, retrying while it fails with `SpotifyError::RateLimited`
+	pub async fn run<T, F, Fut>(&self, mut request: F) -> Result<T, SpotifyError>
+	where
+		F: FnMut() -> Fut,
+		Fut: Future<Output = Result<T, SpotifyError>>,
+	{
+		let mut attempt = 0;
+		loop {
+			self.wait().await;
+			let result = {
+				let _permit = self
+					.permits
+					.acquire()
+					.await
+					.map_err(|_| SpotifyError::Error("Rate limiter closed".into()))?;
+				request().await
+			};
+			match result {
+				Err(SpotifyError::RateLimited(retry_after)) if attempt < self.max_retries => {
+					let wait = match retry_after {
+						Some(seconds) => Duration::from_secs(seconds),
+						None => DEFAULT_RETRY_AFTER * 2u32.pow(attempt as u32),
+					};
+					attempt += 1;
+					warn!(
+						"Rate limited, retrying in {}s ({}/{})",
+						wait.as_secs(),
+						attempt,
+						self.max_retries
+					);
+					self.block_for(wait).await;
+				}
+				result => return result,
+			}
+		}
+	}
+
+	/// Send HTTP request, retrying on 429 after Retry-After
+	pub async fn send(&self, request: RequestBuilder) -> Result<Response, SpotifyError> {
+		self.run(|| {
+			let request = request.try_clone();
+			async move {
+				let request = request
+					.ok_or_else(|| SpotifyError::Error("Request can't be retried".into()))?;
+				let response = request.send().await?;
+				if response.status() == StatusCode::TOO_MANY_REQUESTS {
+					return Err(SpotifyError::RateLimited(retry_after(&response)));
+				}
+				Ok(response)
+			}
+		})
+		.await
+	}
+
+	/// Wait until the rate limit is over
+	async fn wait(&self) {
+		let until = *self.blocked_until.lock().await;
+		if let Some(until) = until {
+			let now = Instant::now();
+			if until > now {
+				async_std::task::sleep(until - now).await;
+			}
+		}
+	}
+
+	/// Pause all requests for duration
+	async fn block_for(&self, duration: Duration) {
+		let until = Instant::now() + duration;
+		let mut blocked_until = self.blocked_until.lock().await;
+		if blocked_until.map(|b| b < until).unwrap_or(true) {
+			*blocked_until = Some(until);
+		}
+	}
+}
+
+/// Seconds of the Retry-After header
+fn retry_after(response: &Response) -> Option<u64> {
+	response
+		.headers()
+		.get(RETRY_AFTER)?
+		.to_str()
+		.ok()?
+		.trim()
+		.parse()
+		.ok()
+}
@@ -0,0 +1,72 @@
+use std::io::{ErrorKind, Read};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::error::SpotifyError;
+
+/// Size of a single read
+const CHUNK_SIZE: usize = 1024 * 64;
+/// Chunks read ahead of the consumer
+const CHUNK_BUFFER: usize = 4;
+
+/// Reads on a dedicated blocking thread, passing filled buffers through a bounded channel
+pub struct BlockingReader<R> {
+	chunks: mpsc::Receiver<Result<Vec<u8>, SpotifyError>>,
+	recycle: mpsc::Sender<Vec<u8>>,
+	handle: JoinHandle<R>,
+}
+
+impl<R: Read + Send + 'static> BlockingReader<R> {
+	/// Start reading
+	pub fn new(mut read: R) -> BlockingReader<R> {
+		let (chunks_tx, chunks) = mpsc::channel(CHUNK_BUFFER);
+		// Every buffer is either queued, being written or waiting to be reused
+		let (recycle, mut recycle_rx) = mpsc::channel::<Vec<u8>>(CHUNK_BUFFER + 2);
+
+		let handle = tokio::task::spawn_blocking(move || {
+			loop {
+				let mut buf = recycle_rx
+					.try_recv()
+					.unwrap_or_else(|_| Vec::with_capacity(CHUNK_SIZE));
+				buf.resize(CHUNK_SIZE, 0);
+				let result = match read.read(&mut buf) {
+					Ok(0) => break,
+					Ok(read) => {
+						buf.truncate(read);
+						Ok(buf)
+					}
+					Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+					Err(e) => Err(e.into()),
+				};
+				let end = result.is_err();
+				// Consumer is gone
+				if chunks_tx.blocking_send(result).is_err() || end {
+					break;
+				}
+			}
+			read
+		});
+
+		BlockingReader {
+			chunks,
+			recycle,
+			handle,
+		}
+	}
+
+	/// Next filled buffer, `None` at EOF
+	pub async fn next(&mut self) -> Option<Result<Vec<u8>, SpotifyError>> {
+		self.chunks.recv().await
+	}
+
+	/// Give buffer back for reuse
+	pub fn recycle(&self, buf: Vec<u8>) {
+		self.recycle.try_send(buf).ok();
+	}
+
+	/// Wait for the reader thread and get the reader back
+	pub async fn finish(self) -> Result<R, SpotifyError> {
+		drop(self.chunks);
+		Ok(self.handle.await?)
+	}
+}
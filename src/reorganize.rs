@@ -0,0 +1,421 @@
+use sanitize_filename::sanitize;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use crate::downloader::{ArtistsTag, AudioFormat, DownloaderConfig, DownloaderInternal};
+use crate::error::SpotifyError;
+use crate::retag::Retagger;
+use crate::tag::{Field, TagWrap};
+
+/// Extensions of lyrics files moved along with the track of the same name
+const LYRICS_EXTENSIONS: [&str; 2] = ["lrc", "txt"];
+/// Cover files moved along with the tracks of a directory
+const COVER_FILENAMES: [&str; 2] = ["cover.jpg", "folder.jpg"];
+
+/// Moves already downloaded files into the layout of the current templates
+pub struct Reorganizer {
+	config: DownloaderConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Move {
+	pub from: PathBuf,
+	pub to: PathBuf,
+}
+
+/// Multiple files which would end up at the same path, or a target which already exists
+#[derive(Debug, Clone)]
+pub struct Collision {
+	pub target: PathBuf,
+	pub sources: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaylistUpdate {
+	pub path: PathBuf,
+	pub old: String,
+	pub new: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+	pub moves: Vec<Move>,
+	pub playlists: Vec<PlaylistUpdate>,
+	pub collisions: Vec<Collision>,
+	pub errors: Vec<(PathBuf, SpotifyError)>,
+}
+
+/// Everything needed to revert an executed plan
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UndoLog {
+	pub moves: Vec<Move>,
+	pub playlists: Vec<PlaylistUpdate>,
+}
+
+impl Reorganizer {
+	/// Create new instance
+	pub fn new(config: DownloaderConfig) -> Reorganizer {
+		Reorganizer { config }
+	}
+
+	/// Plan moving all files in directory ( BLOCKING )
+	pub fn plan(&self, path: impl AsRef<Path>) -> Result<Plan, SpotifyError> {
+		let root = std::fs::canonicalize(path)?;
+		let mut plan = Plan::default();
+
+		// Target of every track
+		let mut targets: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+		for file in Retagger::find_files(&root)? {
+			match self.target(&file) {
+				Ok(target) => targets.entry(target).or_default().push(file),
+				Err(e) => plan.errors.push((file, e)),
+			}
+		}
+		let sources: HashSet<PathBuf> = targets.values().flatten().cloned().collect();
+
+		let mut moves = vec![];
+		for (target, sources_) in targets {
+			if sources_.len() > 1 || (target.exists() && !sources.contains(&target)) {
+				plan.collisions.push(Collision {
+					target,
+					sources: sources_,
+				});
+				continue;
+			}
+			let from = sources_.into_iter().next().unwrap();
+			if from == target {
+				continue;
+			}
+
+			// Lyrics with the same name
+			for extension in LYRICS_EXTENSIONS {
+				let lyrics = from.with_extension(extension);
+				if lyrics.is_file() {
+					moves.push(Move {
+						from: lyrics,
+						to: target.with_extension(extension),
+					});
+				}
+			}
+			moves.push(Move { from, to: target });
+		}
+
+		// Covers go to the directory of the first track moved out of their directory
+		let mut directories = HashSet::new();
+		let mut covers = vec![];
+		for m in &moves {
+			let (from, to) = match (m.from.parent(), m.to.parent()) {
+				(Some(from), Some(to)) if from != to => (from, to),
+				_ => continue,
+			};
+			if !directories.insert(from.to_owned()) {
+				continue;
+			}
+			for filename in self.cover_filenames() {
+				if from.join(&filename).is_file() {
+					covers.push(Move {
+						from: from.join(&filename),
+						to: to.join(&filename),
+					});
+				}
+			}
+		}
+		moves.append(&mut covers);
+
+		// Sidecar files must not overwrite anything or each other, files which are moved away are fine
+		let moved: HashSet<PathBuf> = moves.iter().map(|m| m.from.clone()).collect();
+		let mut sidecars: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+		for m in moves {
+			sidecars.entry(m.to).or_default().push(m.from);
+		}
+		let mut checked = vec![];
+		for (to, mut sources) in sidecars {
+			if sources.len() > 1 || (to.exists() && !moved.contains(&to)) {
+				sources.sort();
+				plan.collisions.push(Collision {
+					target: to,
+					sources,
+				});
+			} else {
+				checked.push(Move {
+					from: sources.remove(0),
+					to,
+				});
+			}
+		}
+		checked.sort_by(|a, b| a.from.cmp(&b.from));
+		plan.moves = Reorganizer::order_moves(checked);
+
+		plan.playlists = Reorganizer::plan_playlists(&root, &plan.moves)?;
+		Ok(plan)
+	}
+
+	/// Move files and update playlists, returns the undo log ( BLOCKING )
+	///
+	/// The undo log is written to `log_path` even if a move fails midway.
+	pub fn execute(
+		&self,
+		plan: &Plan,
+		log_path: impl AsRef<Path>,
+	) -> Result<UndoLog, SpotifyError> {
+		let mut log = UndoLog::default();
+		let result = Reorganizer::apply(&plan.moves, &plan.playlists, &mut log);
+		std::fs::write(&log_path, serde_json::to_string_pretty(&log)?)?;
+		result?;
+
+		// Cleanup directories left empty
+		for m in &log.moves {
+			if let Some(parent) = m.from.parent() {
+				std::fs::remove_dir(parent).ok();
+			}
+		}
+		Ok(log)
+	}
+
+	/// Revert moves and playlist updates of an undo log ( BLOCKING )
+	pub fn undo(log_path: impl AsRef<Path>) -> Result<UndoLog, SpotifyError> {
+		let data = std::fs::read_to_string(&log_path)?;
+		let log: UndoLog = serde_json::from_str(&data)?;
+
+		let moves: Vec<Move> = log
+			.moves
+			.iter()
+			.rev()
+			.map(|m| Move {
+				from: m.to.clone(),
+				to: m.from.clone(),
+			})
+			.collect();
+		let playlists: Vec<PlaylistUpdate> = log
+			.playlists
+			.iter()
+			.map(|p| PlaylistUpdate {
+				path: p.path.clone(),
+				old: p.new.clone(),
+				new: p.old.clone(),
+			})
+			.collect();
+
+		let mut reverted = UndoLog::default();
+		Reorganizer::apply(&moves, &playlists, &mut reverted)?;
+		Ok(reverted)
+	}
+
+	/// Apply moves and playlist updates, recording everything done
+	fn apply(
+		moves: &[Move],
+		playlists: &[PlaylistUpdate],
+		log: &mut UndoLog,
+	) -> Result<(), SpotifyError> {
+		for m in moves {
+			if m.to.exists() {
+				return Err(SpotifyError::Error(format!(
+					"{} already exists",
+					m.to.to_string_lossy()
+				)));
+			}
+			if let Some(parent) = m.to.parent() {
+				std::fs::create_dir_all(parent)?;
+			}
+			// Rename doesn't work across filesystems
+			if std::fs::rename(&m.from, &m.to).is_err() {
+				std::fs::copy(&m.from, &m.to)?;
+				std::fs::remove_file(&m.from)?;
+			}
+			log.moves.push(m.clone());
+		}
+		for playlist in playlists {
+			std::fs::write(&playlist.path, &playlist.new)?;
+			log.playlists.push(playlist.clone());
+		}
+		Ok(())
+	}
+
+	/// Order moves so every target is free when it's moved to ( A -> B before B -> C )
+	///
+	/// Cycles are broken by moving one of the files to a temporary name first.
+	fn order_moves(mut pending: Vec<Move>) -> Vec<Move> {
+		let mut ordered = vec![];
+		while !pending.is_empty() {
+			let sources: HashSet<&PathBuf> = pending.iter().map(|m| &m.from).collect();
+			match pending.iter().position(|m| !sources.contains(&m.to)) {
+				Some(i) => ordered.push(pending.remove(i)),
+				None => {
+					let mut temporary = pending[0].from.clone();
+					let mut n = 0;
+					while temporary.exists() || sources.contains(&temporary) {
+						n += 1;
+						temporary = pending[0].from.with_extension(format!("reorganize-{}", n));
+					}
+					ordered.push(Move {
+						from: pending[0].from.clone(),
+						to: temporary.clone(),
+					});
+					pending[0].from = temporary;
+				}
+			}
+		}
+		ordered
+	}
+
+	/// Render the current templates for file
+	fn target(&self, path: &Path) -> Result<PathBuf, SpotifyError> {
+		let format = AudioFormat::from_path(path);
+		let extension = format.extension();
+		let tag_wrap = TagWrap::new(path, format.clone())?;
+		let tag = tag_wrap.tag();
+		let track_id = tag
+			.get_unique_file_identifier()
+			.ok_or_else(|| SpotifyError::Error("Missing Spotify track ID".into()))?;
+
+		let first = |field: Field| tag.get_field(field).into_iter().next().unwrap_or_default();
+		let number = |field: Field| {
+			first(field)
+				.split('/')
+				.next()
+				.and_then(|n| n.trim().parse::<u32>().ok())
+				.unwrap_or(0)
+		};
+		// Only a single joined value is split, names may contain the separator themselves
+		let separator = self.config.separator.as_str();
+		let list = |field: Field| -> Vec<String> {
+			let joined = self.is_joined(&format, &field);
+			let values = tag.get_field(field);
+			if !joined || separator.is_empty() || values.len() != 1 {
+				return values;
+			}
+			values[0]
+				.split(separator)
+				.map(|v| v.trim().to_string())
+				.filter(|v| !v.is_empty())
+				.collect()
+		};
+		let mut artists = list(Field::Artists);
+		if artists.is_empty() {
+			artists = list(Field::Artist);
+		}
+		let album_artists = list(Field::AlbumArtist);
+
+		let values = vec![
+			("%title%", sanitize(first(Field::Title))),
+			(
+				"%artist%",
+				sanitize(artists.first().cloned().unwrap_or_default()),
+			),
+			("%artists%", sanitize(artists.join(", "))),
+			("%track%", number(Field::TrackNumber).to_string()),
+			("%0track%", format!("{:02}", number(Field::TrackNumber))),
+			("%disc%", number(Field::DiscNumber).to_string()),
+			("%0disc%", format!("{:02}", number(Field::DiscNumber))),
+			("%id%", track_id),
+			("%album%", sanitize(first(Field::Album))),
+			(
+				"%albumArtist%",
+				sanitize(album_artists.first().cloned().unwrap_or_default()),
+			),
+			("%albumArtists%", sanitize(album_artists.join(", "))),
+		];
+		let target = DownloaderInternal::render_path(&self.config, &values);
+		let target = PathBuf::from(format!("{}.{}", target.to_string_lossy(), extension));
+		Reorganizer::canonicalize(&target)
+	}
+
+	/// Whether the downloader wrote field as one value joined with the separator
+	fn is_joined(&self, format: &AudioFormat, field: &Field) -> bool {
+		// ID3v2.3 has no multiple values
+		if matches!(format, AudioFormat::Mp3) && !self.config.id3v24 {
+			return true;
+		}
+		matches!(field, Field::Artist) && self.config.artists_tag != ArtistsTag::Multiple
+	}
+
+	/// Canonicalize the existing part of path, the same way as the scanned root
+	fn canonicalize(path: &Path) -> Result<PathBuf, SpotifyError> {
+		let path = std::env::current_dir()?.join(path);
+		let mut existing = path.as_path();
+		let mut missing = vec![];
+		while !existing.exists() {
+			match (existing.parent(), existing.file_name()) {
+				(Some(parent), Some(name)) => {
+					missing.push(name);
+					existing = parent;
+				}
+				_ => break,
+			}
+		}
+		let mut canonical = std::fs::canonicalize(existing)?;
+		canonical.extend(missing.into_iter().rev());
+		Ok(canonical)
+	}
+
+	/// Cover filenames moved along with the tracks
+	fn cover_filenames(&self) -> Vec<String> {
+		let mut filenames: Vec<String> = COVER_FILENAMES.iter().map(|f| f.to_string()).collect();
+		if let Some(sidecar) = &self.config.cover.sidecar {
+			if !filenames.contains(sidecar) {
+				filenames.push(sidecar.to_string());
+			}
+		}
+		filenames
+	}
+
+	/// Rewrite references to moved files in all M3U playlists in directory
+	fn plan_playlists(root: &Path, moves: &[Move]) -> Result<Vec<PlaylistUpdate>, SpotifyError> {
+		let moves: HashMap<&Path, &Path> = moves
+			.iter()
+			.map(|m| (m.from.as_path(), m.to.as_path()))
+			.collect();
+
+		let mut updates = vec![];
+		for path in Reorganizer::find_playlists(root)? {
+			let directory = path.parent().unwrap_or(root);
+			let old = std::fs::read_to_string(&path)?;
+			let new = old
+				.lines()
+				.map(|line| {
+					if line.trim().is_empty() || line.starts_with('#') {
+						return line.to_string();
+					}
+					let entry = Path::new(line.trim());
+					match moves.get(directory.join(entry).as_path()) {
+						// Keep relative entries relative
+						Some(to) if entry.is_relative() => to
+							.strip_prefix(directory)
+							.unwrap_or(to)
+							.to_string_lossy()
+							.to_string(),
+						Some(to) => to.to_string_lossy().to_string(),
+						None => line.to_string(),
+					}
+				})
+				.collect::<Vec<String>>()
+				.join("\n");
+			let new = match old.ends_with('\n') {
+				true => format!("{}\n", new),
+				false => new,
+			};
+			if new != old {
+				updates.push(PlaylistUpdate { path, old, new });
+			}
+		}
+		Ok(updates)
+	}
+
+	/// Find all M3U playlists in directory recursively
+	fn find_playlists(path: &Path) -> Result<Vec<PathBuf>, SpotifyError> {
+		let mut files = vec![];
+		for entry in std::fs::read_dir(path)? {
+			let path = entry?.path();
+			if path.is_dir() {
+				files.append(&mut Reorganizer::find_playlists(&path)?);
+			} else if matches!(
+				path.extension().and_then(|e| e.to_str()),
+				Some("m3u") | Some("m3u8")
+			) {
+				files.push(path);
+			}
+		}
+		Ok(files)
+	}
+}
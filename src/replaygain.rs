@@ -0,0 +1,214 @@
+use std::f64::consts::PI;
+use std::path::Path;
+
+use crate::decoder::decode_file;
+use crate::downloader::AudioFormat;
+use crate::error::SpotifyError;
+
+/// ReplayGain 2.0 reference loudness in LUFS
+pub(crate) const REFERENCE_LOUDNESS: f64 = -18.0;
+/// Gates of EBU R128
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Biquad filter, transposed direct form II
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+	b: [f64; 3],
+	a: [f64; 3],
+	z: [f64; 2],
+}
+
+impl Biquad {
+	fn process(&mut self, x: f64) -> f64 {
+		let y = self.b[0] * x + self.z[0];
+		self.z[0] = self.b[1] * x - self.a[1] * y + self.z[1];
+		self.z[1] = self.b[2] * x - self.a[2] * y;
+		y
+	}
+}
+
+/// K-weighting filter (high shelf and high pass) of ITU-R BS.1770 for any sample rate
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+	let rate = sample_rate as f64;
+
+	// High shelf
+	let f0 = 1681.974450955533;
+	let g = 3.999843853973347;
+	let q = 0.7071752369554196;
+	let k = (PI * f0 / rate).tan();
+	let vh = 10f64.powf(g / 20.0);
+	let vb = vh.powf(0.4996667741545416);
+	let a0 = 1.0 + k / q + k * k;
+	let shelf = Biquad {
+		b: [
+			(vh + vb * k / q + k * k) / a0,
+			2.0 * (k * k - vh) / a0,
+			(vh - vb * k / q + k * k) / a0,
+		],
+		a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+		z: [0.0; 2],
+	};
+
+	// High pass
+	let f0 = 38.13547087602444;
+	let q = 0.5003270373238773;
+	let k = (PI * f0 / rate).tan();
+	let a0 = 1.0 + k / q + k * k;
+	let pass = Biquad {
+		b: [1.0, -2.0, 1.0],
+		a: [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+		z: [0.0; 2],
+	};
+
+	[shelf, pass]
+}
+
+/// EBU R128 loudness meter
+pub struct LoudnessMeter {
+	sample_rate: u32,
+	filters: Vec<[Biquad; 2]>,
+	/// Summed mean square of every 100ms
+	sub_blocks: Vec<f64>,
+	energy: f64,
+	samples: usize,
+	peak: f32,
+}
+
+impl LoudnessMeter {
+	/// Create new instance
+	pub fn new(sample_rate: u32) -> LoudnessMeter {
+		LoudnessMeter {
+			sample_rate,
+			filters: vec![],
+			sub_blocks: vec![],
+			energy: 0.0,
+			samples: 0,
+			peak: 0.0,
+		}
+	}
+
+	/// Add planar samples
+	pub fn add(&mut self, packet: &[Vec<i16>]) {
+		while self.filters.len() < packet.len() {
+			self.filters.push(k_weighting(self.sample_rate));
+		}
+		let length = packet.iter().map(|c| c.len()).min().unwrap_or(0);
+		let sub_block_length = (self.sample_rate / 10).max(1) as usize;
+
+		for i in 0..length {
+			for (channel, filters) in packet.iter().zip(self.filters.iter_mut()) {
+				let sample = channel[i] as f64 / 32768.0;
+				self.peak = self.peak.max(sample.abs() as f32);
+				let shelved = filters[0].process(sample);
+				let weighted = filters[1].process(shelved);
+				self.energy += weighted * weighted;
+			}
+			self.samples += 1;
+			if self.samples == sub_block_length {
+				self.sub_blocks.push(self.energy / sub_block_length as f64);
+				self.energy = 0.0;
+				self.samples = 0;
+			}
+		}
+	}
+
+	/// Get loudness of everything added
+	pub fn finish(self) -> TrackLoudness {
+		// 400ms blocks with 75% overlap
+		let blocks = self
+			.sub_blocks
+			.windows(4)
+			.map(|w| w.iter().sum::<f64>() / 4.0)
+			.collect();
+		TrackLoudness {
+			blocks,
+			peak: self.peak,
+		}
+	}
+}
+
+/// Gated blocks and peak of a track
+#[derive(Debug, Clone)]
+pub struct TrackLoudness {
+	blocks: Vec<f64>,
+	pub peak: f32,
+}
+
+impl TrackLoudness {
+	/// Integrated loudness in LUFS
+	pub fn loudness(&self) -> f64 {
+		integrated_loudness(&self.blocks)
+	}
+
+	/// ReplayGain 2.0 gain in dB
+	pub fn gain(&self) -> f64 {
+		REFERENCE_LOUDNESS - self.loudness()
+	}
+
+	/// ReplayGain tags of track
+	pub fn track_tags(&self) -> Vec<(&'static str, String)> {
+		vec![
+			("REPLAYGAIN_TRACK_GAIN", format_gain(self.gain())),
+			("REPLAYGAIN_TRACK_PEAK", format_peak(self.peak)),
+		]
+	}
+}
+
+/// ReplayGain tags of album, gated over the blocks of all tracks
+pub fn album_tags(tracks: &[TrackLoudness]) -> Vec<(&'static str, String)> {
+	let blocks: Vec<f64> = tracks.iter().flat_map(|t| t.blocks.clone()).collect();
+	let peak = tracks.iter().map(|t| t.peak).fold(0.0, f32::max);
+	vec![
+		(
+			"REPLAYGAIN_ALBUM_GAIN",
+			format_gain(REFERENCE_LOUDNESS - integrated_loudness(&blocks)),
+		),
+		("REPLAYGAIN_ALBUM_PEAK", format_peak(peak)),
+	]
+}
+
+/// Decode file and measure its loudness ( BLOCKING )
+pub fn analyze_file(
+	path: impl AsRef<Path>,
+	format: AudioFormat,
+) -> Result<TrackLoudness, SpotifyError> {
+	let mut meter: Option<LoudnessMeter> = None;
+	decode_file(path, format, |packet, sample_rate| {
+		meter
+			.get_or_insert_with(|| LoudnessMeter::new(sample_rate))
+			.add(packet)
+	})?;
+	meter
+		.map(|m| m.finish())
+		.ok_or_else(|| SpotifyError::Error("No audio to analyze".into()))
+}
+
+/// Gated loudness of blocks, silence is clamped to the absolute gate
+fn integrated_loudness(blocks: &[f64]) -> f64 {
+	let to_loudness = |energy: f64| -0.691 + 10.0 * energy.log10();
+	let mean = |blocks: &[f64]| blocks.iter().sum::<f64>() / blocks.len() as f64;
+
+	let gated: Vec<f64> = blocks
+		.iter()
+		.copied()
+		.filter(|b| to_loudness(*b) > ABSOLUTE_GATE)
+		.collect();
+	if gated.is_empty() {
+		return ABSOLUTE_GATE;
+	}
+	let threshold = to_loudness(mean(&gated)) + RELATIVE_GATE;
+	let gated: Vec<f64> = gated
+		.into_iter()
+		.filter(|b| to_loudness(*b) > threshold)
+		.collect();
+	to_loudness(mean(&gated))
+}
+
+pub(crate) fn format_gain(gain: f64) -> String {
+	format!("{:.2} dB", gain)
+}
+
+pub(crate) fn format_peak(peak: f32) -> String {
+	format!("{:.6}", peak)
+}
@@ -0,0 +1,100 @@
+use down_on_spot::{Download, DownloadState, SpotifyError};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::fs::{create_dir_all, File};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::output;
+
+/// Download which failed, with enough context to retry it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Failure {
+	/// `spotify:track:` URI
+	pub uri: String,
+	pub title: String,
+	/// URI the track was queued from
+	pub source: Option<String>,
+	pub error: String,
+	pub error_kind: String,
+}
+
+/// Outcome of a run, written when it ends
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+	/// RFC 3339 time the run ended
+	pub finished: String,
+	/// Input of the run
+	pub input: String,
+	pub total: usize,
+	pub done: usize,
+	pub skipped: usize,
+	pub failed: usize,
+	pub failures: Vec<Failure>,
+}
+
+impl Report {
+	/// Create report of downloads, counting unfinished ones as failed
+	pub fn new(input: &str, downloads: &[Download]) -> Report {
+		let failures: Vec<Failure> = downloads
+			.iter()
+			.filter_map(|d| {
+				let (error, error_kind) = match &d.state {
+					DownloadState::Error(e) if output::is_failed(d) => {
+						(e.to_string(), e.kind().to_string())
+					}
+					DownloadState::Done | DownloadState::Error(_) => return None,
+					// Left when quitting early
+					_ => ("Not finished".to_string(), "unfinished".to_string()),
+				};
+				Some(Failure {
+					uri: format!("spotify:track:{}", d.track_id),
+					title: d.title.clone(),
+					source: d.source.clone(),
+					error,
+					error_kind,
+				})
+			})
+			.collect();
+		let count = |state: &DownloadState| downloads.iter().filter(|d| &d.state == state).count();
+		Report {
+			finished: chrono::Local::now().to_rfc3339(),
+			input: input.to_string(),
+			total: downloads.len(),
+			done: count(&DownloadState::Done),
+			skipped: count(&DownloadState::Error(SpotifyError::AlreadyDownloaded)),
+			failed: failures.len(),
+			failures,
+		}
+	}
+
+	/// Path of the list of failed URIs belonging to the report
+	pub fn failed_list_path(path: &Path) -> PathBuf {
+		let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+		path.with_file_name(format!("{}_failed.txt", stem))
+	}
+
+	/// Write report as JSON and the failed URIs, one per line, next to it
+	pub async fn save(&self, path: &Path) -> Result<(), SpotifyError> {
+		if let Some(parent) = path.parent() {
+			create_dir_all(parent).await?;
+		}
+		let mut file = File::create(path).await?;
+		file.write_all(serde_json::to_string_pretty(self)?.as_bytes())
+			.await?;
+
+		let mut list = File::create(Report::failed_list_path(path)).await?;
+		for failure in &self.failures {
+			list.write_all(format!("{}\n", failure.uri).as_bytes())
+				.await?;
+		}
+		Ok(())
+	}
+
+	/// Read report written by `save`
+	pub async fn load(path: &Path) -> Result<Report, SpotifyError> {
+		let mut file = File::open(path).await?;
+		let mut buf = String::new();
+		file.read_to_string(&mut buf).await?;
+		Ok(serde_json::from_str(&buf)?)
+	}
+}
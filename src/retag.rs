@@ -0,0 +1,131 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::cover::CoverCache;
+use crate::downloader::{AudioFormat, DownloaderConfig, DownloaderInternal};
+use crate::error::SpotifyError;
+use crate::metadata::MetadataProvider;
+use crate::tag::{Field, TagWrap};
+
+/// Rewrites tags of already downloaded files with fresh metadata
+pub struct Retagger {
+	metadata: Arc<dyn MetadataProvider>,
+	config: DownloaderConfig,
+	covers: CoverCache,
+}
+
+/// Changed value of a field, the release date or the cover
+#[derive(Debug, Clone)]
+pub struct TagChange {
+	pub name: String,
+	pub old: Vec<String>,
+	pub new: Vec<String>,
+}
+
+impl Retagger {
+	/// Create new instance
+	pub fn new(metadata: Arc<dyn MetadataProvider>, config: DownloaderConfig) -> Retagger {
+		Retagger {
+			metadata,
+			config,
+			covers: CoverCache::default(),
+		}
+	}
+
+	/// Find all taggable files in directory recursively
+	pub fn find_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>, SpotifyError> {
+		let mut files = vec![];
+		for entry in std::fs::read_dir(path)? {
+			let path = entry?.path();
+			if path.is_dir() {
+				files.append(&mut Retagger::find_files(&path)?);
+			} else if matches!(
+				AudioFormat::from_path(&path),
+				AudioFormat::Ogg | AudioFormat::Mp3
+			) {
+				files.push(path);
+			}
+		}
+		files.sort();
+		Ok(files)
+	}
+
+	/// Retag file, returns changed fields. Nothing is written if `dry_run` is set.
+	pub async fn retag(
+		&self,
+		path: impl AsRef<Path>,
+		dry_run: bool,
+	) -> Result<Vec<TagChange>, SpotifyError> {
+		let path = path.as_ref().to_owned();
+		let format = AudioFormat::from_path(&path);
+		let mut tag_wrap =
+			tokio::task::spawn_blocking(move || TagWrap::new(path, format)).await??;
+		let track_id = tag_wrap
+			.tag()
+			.get_unique_file_identifier()
+			.ok_or_else(|| SpotifyError::Error("Missing Spotify track ID".into()))?;
+
+		// Fetch metadata
+		let track = self.metadata.get_track(&track_id).await?;
+		let album = self
+			.metadata
+			.get_album(track.album.id.as_ref().ok_or(SpotifyError::Unavailable)?)
+			.await?;
+
+		let mut cover = None;
+		if self.config.cover.embed {
+			if let Some(image) = self.config.cover.select(&track.album.images) {
+				match self
+					.covers
+					.get(&album.id, &image.url, &self.config.cover)
+					.await
+				{
+					Ok(c) => cover = Some(c),
+					Err(e) if self.config.cover.required => return Err(e),
+					Err(e) => warn!("Failed downloading cover! {}", e),
+				}
+			}
+		}
+
+		let old = Retagger::fields(&tag_wrap);
+		DownloaderInternal::apply_tags(
+			&mut tag_wrap,
+			&track_id,
+			DownloaderInternal::track_tags(&track, &album),
+			album.release_date,
+			cover,
+			&self.config,
+		);
+		let changes = old
+			.into_iter()
+			.zip(Retagger::fields(&tag_wrap))
+			.filter(|((_, old), (_, new))| old != new)
+			.map(|((name, old), (_, new))| TagChange { name, old, new })
+			.collect();
+
+		if !dry_run {
+			tokio::task::spawn_blocking(move || tag_wrap.get_tag().save()).await??;
+		}
+		Ok(changes)
+	}
+
+	/// Get values of all fields, the release date and a summary of the cover
+	fn fields(tag_wrap: &TagWrap) -> Vec<(String, Vec<String>)> {
+		let tag = tag_wrap.tag();
+		let mut fields: Vec<(String, Vec<String>)> = Field::all()
+			.into_iter()
+			.map(|field| (format!("{:?}", field), tag.get_field(field)))
+			.collect();
+		fields.push(("Date".into(), tag.get_release_date().into_iter().collect()));
+		// Images are compared by their hash
+		let cover = tag.get_cover().map(|(mime, data)| {
+			let mut hasher = DefaultHasher::new();
+			data.hash(&mut hasher);
+			format!("{}, {} bytes, {:016x}", mime, data.len(), hasher.finish())
+		});
+		fields.push(("Cover".into(), cover.into_iter().collect()));
+		fields
+	}
+}
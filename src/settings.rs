@@ -1,6 +1,5 @@
-use crate::downloader::DownloaderConfig;
-use crate::error::SpotifyError;
 use aspotify::CountryCode;
+use down_on_spot::{DownloaderConfig, SpotifyError};
 use serde::{Deserialize, Serialize};
 
 use tokio::{
@@ -51,6 +50,11 @@ pub fn get_config_settings_path() -> PathBuf {
 	get_config_folder_path().join("settings.json")
 }
 
+/// Returns the full path to the report of the last run, next to the Settings json
+pub fn get_last_run_path() -> PathBuf {
+	get_config_folder_path().join("last_run.json")
+}
+
 impl Settings {
 	// Create new instance
 	pub fn new(username: &str, password: &str, client_id: &str, client_secret: &str) -> Settings {
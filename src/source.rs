@@ -0,0 +1,106 @@
+use async_trait::async_trait;
+use librespot::audio::{AudioDecrypt, AudioFile};
+use librespot::core::session::Session;
+use librespot::core::spotify_id::SpotifyId;
+use librespot::metadata::{Metadata, Track};
+use librespot::protocol::metadata::audio_file::Format as FileFormat;
+use std::io::Read;
+
+use crate::downloader::AudioFormat;
+use crate::error::SpotifyError;
+use crate::normalisation::NormalisationData;
+
+/// Opened audio file of track
+pub struct AudioStream {
+	/// Plain audio data, without any Spotify header
+	pub reader: Box<dyn Read + Send>,
+	/// Size in bytes, used for progress
+	pub size: usize,
+	/// Loudness data shipped with the file
+	pub normalisation: Option<NormalisationData>,
+}
+
+/// Source of the audio data of tracks
+#[async_trait]
+pub trait AudioSource: Send + Sync {
+	/// Get formats the track is available in
+	async fn formats(&self, track_id: &str) -> Result<Vec<FileFormat>, SpotifyError>;
+
+	/// Open track in format
+	async fn open(&self, track_id: &str, format: FileFormat) -> Result<AudioStream, SpotifyError>;
+}
+
+/// Audio streamed and decrypted by librespot
+#[derive(Clone)]
+pub struct LibrespotSource {
+	session: Session,
+}
+
+impl LibrespotSource {
+	/// Create new instance
+	pub fn new(session: Session) -> LibrespotSource {
+		LibrespotSource { session }
+	}
+
+	/// Get track or its playable alternative
+	async fn track(&self, track_id: &str) -> Result<Track, SpotifyError> {
+		let id = SpotifyId::from_base62(track_id)?;
+		let track = Track::get(&self.session, &id).await?;
+		// Fallback if unavailable
+		if LibrespotSource::has_alternatives(&track) {
+			return self.find_alternative(track).await;
+		}
+		Ok(track)
+	}
+
+	async fn find_alternative(&self, track: Track) -> Result<Track, SpotifyError> {
+		let librespot::metadata::track::Tracks(ids) = track.alternatives;
+		for id in ids {
+			let t = Track::get(&self.session, &id).await?;
+			if !LibrespotSource::has_alternatives(&t) {
+				return Ok(t);
+			}
+		}
+
+		Err(SpotifyError::Unavailable)
+	}
+
+	fn has_alternatives(track: &Track) -> bool {
+		let librespot::metadata::track::Tracks(alts) = &track.alternatives;
+		!alts.is_empty()
+	}
+}
+
+#[async_trait]
+impl AudioSource for LibrespotSource {
+	async fn formats(&self, track_id: &str) -> Result<Vec<FileFormat>, SpotifyError> {
+		let track = self.track(track_id).await?;
+		Ok(track.files.keys().copied().collect())
+	}
+
+	async fn open(&self, track_id: &str, format: FileFormat) -> Result<AudioStream, SpotifyError> {
+		let track = self.track(track_id).await?;
+		let file_id = *track.files.get(&format).ok_or(SpotifyError::Unavailable)?;
+
+		let key = self.session.audio_key().request(track.id, file_id).await?;
+		let encrypted = AudioFile::open(&self.session, file_id, 1024 * 1024).await?;
+		let size = encrypted.get_stream_loader_controller()?.len();
+		let mut decrypted = AudioDecrypt::new(Some(key), encrypted);
+		// Only Ogg files are prefixed with Spotify's header
+		let (decrypted, normalisation) = match AudioFormat::from(format) {
+			AudioFormat::Ogg => {
+				tokio::task::spawn_blocking(move || {
+					let normalisation = NormalisationData::read(&mut decrypted)?;
+					Ok::<_, SpotifyError>((decrypted, normalisation))
+				})
+				.await??
+			}
+			_ => (decrypted, None),
+		};
+		Ok(AudioStream {
+			reader: Box::new(decrypted),
+			size,
+			normalisation,
+		})
+	}
+}
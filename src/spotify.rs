@@ -1,23 +1,76 @@
 use aspotify::{
-	Album, Artist, Client, ClientCredentials, CountryCode, ItemType, Market, Playlist,
-	PlaylistItemType, Track, TrackSimplified,
+	Album, Artist, CountryCode, Market, Playlist, PlaylistItem, PlaylistItemType, Track,
+	TrackSimplified,
 };
+use async_std::sync::Mutex;
 use librespot::core::authentication::Credentials;
 use librespot::core::cache::Cache;
 use librespot::core::config::SessionConfig;
 use librespot::core::session::Session;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::fmt;
 use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
 use url::Url;
 
 use crate::error::SpotifyError;
+use crate::ratelimit::RateLimiter;
+use crate::webapi::{market_query, Page, WebApi};
 
+/// Maximum IDs per request of the Web API
+const TRACKS_PER_REQUEST: usize = 50;
+const ALBUMS_PER_REQUEST: usize = 20;
 
+#[derive(Clone)]
 pub struct Spotify {
 	// librespotify sessopm
 	pub session: Session,
-	pub spotify: Client,
+	/// Shared by all clones, caps concurrent Web API requests
+	api: WebApi,
 	pub market: Option<Market>,
+	cache: MetadataCache,
+}
+
+/// Responses of the Web API wrapping their items
+#[derive(Deserialize)]
+struct Tracks {
+	tracks: Vec<Option<Track>>,
+}
+
+#[derive(Deserialize)]
+struct Albums {
+	albums: Vec<Option<Album>>,
+}
+
+#[derive(Deserialize)]
+struct SearchResults {
+	tracks: Page<Track>,
+}
+
+#[derive(Deserialize)]
+struct AlbumId {
+	id: String,
+}
+
+/// Full tracks and albums by ID, shared by all clones
+#[derive(Clone, Default)]
+struct MetadataCache {
+	tracks: Arc<Mutex<HashMap<String, Track>>>,
+	/// Concurrent requests for the same album wait for the first one
+	albums: Arc<Mutex<HashMap<String, Arc<OnceCell<Album>>>>>,
+}
+
+impl MetadataCache {
+	async fn album_cell(&self, id: &str) -> Arc<OnceCell<Album>> {
+		self.albums
+			.lock()
+			.await
+			.entry(id.to_string())
+			.or_default()
+			.clone()
+	}
 }
 
 impl Spotify {
@@ -39,17 +92,14 @@ impl Spotify {
         let session = Session::new(SessionConfig::default(), Some(cache));
         session.connect(credentials, true).await?;
 
-		//aspotify
-		let credentials = ClientCredentials {
-			id: client_id.to_string(),
-			secret: client_secret.to_string(),
-		};
-		let spotify = Client::new(credentials);
+		// Web API, caps concurrent requests and honours Retry-After
+		let api = WebApi::new(client_id, client_secret, RateLimiter::default());
 
 		Ok(Spotify {
 			session,
-			spotify,
+			api,
 			market: market_country_code.map(Market::Country),
+			cache: MetadataCache::default(),
 		})
 	}
 
@@ -79,133 +129,173 @@ impl Spotify {
 		Err(SpotifyError::InvalidUri)
 	}
 
+	/// Query parameters with the market appended
+	fn query(&self, query: &[(&'static str, String)]) -> Vec<(&'static str, String)> {
+		let mut query = query.to_vec();
+		query.append(&mut market_query(self.market));
+		query
+	}
+
 	/// Fetch data for URI
 	pub async fn resolve_uri(&self, uri: &str) -> Result<SpotifyItem, SpotifyError> {
 		let parts = uri.split(':').skip(1).collect::<Vec<&str>>();
 		let id = parts[1];
 		match parts[0] {
 			"track" => {
-				let track = self.spotify.tracks().get_track(id, self.market).await?;
-				Ok(SpotifyItem::Track(track.data))
+				let track: Track = self
+					.api
+					.get(&format!("/tracks/{}", id), &self.query(&[]))
+					.await?;
+				self.cache_track(track.clone()).await;
+				Ok(SpotifyItem::Track(track))
 			}
 			"playlist" => {
 				let playlist = self
-					.spotify
-					.playlists()
-					.get_playlist(id, self.market)
+					.api
+					.get(&format!("/playlists/{}", id), &self.query(&[]))
 					.await?;
-				Ok(SpotifyItem::Playlist(playlist.data))
+				Ok(SpotifyItem::Playlist(playlist))
 			}
 			"album" => {
-				let album = self.spotify.albums().get_album(id, self.market).await?;
-				Ok(SpotifyItem::Album(album.data))
+				let album: Album = self
+					.api
+					.get(&format!("/albums/{}", id), &self.query(&[]))
+					.await?;
+				// Reused by every track of the album
+				self.cache_album(album.clone()).await;
+				Ok(SpotifyItem::Album(album))
 			}
 			"artist" => {
-				let artist = self.spotify.artists().get_artist(id).await?;
-				Ok(SpotifyItem::Artist(artist.data))
+				let artist = self.api.get(&format!("/artists/{}", id), &[]).await?;
+				Ok(SpotifyItem::Artist(artist))
 			}
 			// Unsupported / Unimplemented
 			_ => Ok(SpotifyItem::Other(uri.to_string())),
 		}
 	}
 
+	/// Get full track, cached
+	pub async fn get_track(&self, id: &str) -> Result<Track, SpotifyError> {
+		if let Some(track) = self.cache.tracks.lock().await.get(id) {
+			return Ok(track.clone());
+		}
+		let track: Track = self.api.get(&format!("/tracks/{}", id), &[]).await?;
+		self.cache_track(track.clone()).await;
+		Ok(track)
+	}
+
+	/// Get full album, cached
+	pub async fn get_album(&self, id: &str) -> Result<Album, SpotifyError> {
+		let cell = self.cache.album_cell(id).await;
+		let album = cell
+			.get_or_try_init(|| self.api.get::<Album>(&format!("/albums/{}", id), &[]))
+			.await?;
+		Ok(album.clone())
+	}
+
+	/// Fetch tracks and their albums which aren't cached yet, in batches
+	pub async fn prefetch(&self, track_ids: &[String]) -> Result<(), SpotifyError> {
+		let missing: Vec<&String> = {
+			let tracks = self.cache.tracks.lock().await;
+			track_ids
+				.iter()
+				.filter(|id| !tracks.contains_key(*id))
+				.collect()
+		};
+		for chunk in missing.chunks(TRACKS_PER_REQUEST) {
+			let ids = chunk.iter().map(|id| id.as_str()).collect::<Vec<_>>();
+			let tracks: Tracks = self.api.get("/tracks", &[("ids", ids.join(","))]).await?;
+			for track in tracks.tracks.into_iter().flatten() {
+				self.cache_track(track).await;
+			}
+		}
+
+		let mut album_ids: Vec<String> = {
+			let tracks = self.cache.tracks.lock().await;
+			track_ids
+				.iter()
+				.filter_map(|id| tracks.get(id)?.album.id.clone())
+				.collect()
+		};
+		album_ids.sort();
+		album_ids.dedup();
+		let mut missing = vec![];
+		for id in album_ids {
+			if !self.cache.album_cell(&id).await.initialized() {
+				missing.push(id);
+			}
+		}
+		for chunk in missing.chunks(ALBUMS_PER_REQUEST) {
+			let albums: Albums = self.api.get("/albums", &[("ids", chunk.join(","))]).await?;
+			for album in albums.albums.into_iter().flatten() {
+				self.cache_album(album).await;
+			}
+		}
+		Ok(())
+	}
+
+	/// Add track to cache
+	pub async fn cache_track(&self, track: Track) {
+		if let Some(id) = track.id.clone() {
+			self.cache.tracks.lock().await.insert(id, track);
+		}
+	}
+
+	/// Add album to cache
+	pub async fn cache_album(&self, album: Album) {
+		self.cache.album_cell(&album.id).await.set(album).ok();
+	}
+
 	/// Get search results for query
 	pub async fn search(&self, query: &str) -> Result<Vec<Track>, SpotifyError> {
-		Ok(self
-			.spotify
-			.search()
-			.search(query, [ItemType::Track], true, 50, 0, None)
-			.await?
-			.data
-			.tracks
-			.unwrap()
-			.items)
+		let query = [
+			("q", query.to_string()),
+			("type", "track".to_string()),
+			("limit", "50".to_string()),
+			("include_external", "audio".to_string()),
+		];
+		let results: SearchResults = self.api.get("/search", &query).await?;
+		Ok(results.tracks.items)
 	}
 
 	/// Get all tracks from playlist
 	pub async fn full_playlist(&self, id: &str) -> Result<Vec<Track>, SpotifyError> {
-		let mut items = vec![];
-		let mut offset = 0;
-		loop {
-			let page = self
-				.spotify
-				.playlists()
-				.get_playlists_items(id, 100, offset, self.market)
-				.await?;
-			items.append(
-				&mut page
-					.data
-					.items
-					.iter()
-					.filter_map(|i| -> Option<Track> {
-						if let Some(PlaylistItemType::Track(t)) = &i.item {
-							Some(t.to_owned())
-						} else {
-							None
-						}
-					})
-					.collect(),
-			);
-
-			// End
-			offset += page.data.items.len();
-			if page.data.total == offset {
-				return Ok(items);
-			}
-		}
+		let items: Vec<PlaylistItem> = self
+			.api
+			.get_all(&format!("/playlists/{}/tracks", id), &self.query(&[]), 100)
+			.await?;
+		Ok(items
+			.into_iter()
+			.filter_map(|i| match i.item {
+				Some(PlaylistItemType::Track(t)) => Some(t),
+				_ => None,
+			})
+			.collect())
 	}
 
 	/// Get all tracks from album
 	pub async fn full_album(&self, id: &str) -> Result<Vec<TrackSimplified>, SpotifyError> {
-		let mut items = vec![];
-		let mut offset = 0;
-		loop {
-			let page = self
-				.spotify
-				.albums()
-				.get_album_tracks(id, 50, offset, self.market)
-				.await?;
-			items.append(&mut page.data.items.to_vec());
-
-			// End
-			offset += page.data.items.len();
-			if page.data.total == offset {
-				return Ok(items);
-			}
-		}
+		self.api
+			.get_all(&format!("/albums/{}/tracks", id), &self.query(&[]), 50)
+			.await
 	}
 
-	/// Get all tracks from artist
-	pub async fn full_artist(&self, id: &str) -> Result<Vec<TrackSimplified>, SpotifyError> {
+	/// Get all tracks from artist, with the ID of their album
+	pub async fn full_artist(
+		&self,
+		id: &str,
+	) -> Result<Vec<(String, TrackSimplified)>, SpotifyError> {
+		let albums: Vec<AlbumId> = self
+			.api
+			.get_all(&format!("/artists/{}/albums", id), &self.query(&[]), 50)
+			.await?;
 		let mut items = vec![];
-		let mut offset = 0;
-		loop {
-			let page = self
-				.spotify
-				.artists()
-				.get_artist_albums(id, None, 50, offset, self.market)
-				.await?;
-
-			for album in &mut page.data.items.iter() {
-				items.append(&mut self.full_album(&album.id).await?)
+		for album in albums {
+			for track in self.full_album(&album.id).await? {
+				items.push((album.id.clone(), track));
 			}
-
-			// End
-			offset += page.data.items.len();
-			if page.data.total == offset {
-				return Ok(items);
-			}
-		}
-	}
-}
-
-impl Clone for Spotify {
-	fn clone(&self) -> Self {
-		Self {
-			session: self.session.clone(),
-			spotify: Client::new(self.spotify.credentials.clone()),
-			market: self.market,
 		}
+		Ok(items)
 	}
 }
 
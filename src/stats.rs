@@ -0,0 +1,272 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::downloader::DownloadState;
+
+/// Time span of the throughput samples
+const WINDOW: Duration = Duration::from_secs(5);
+/// Shortest span speeds are calculated over, so the first chunk doesn't look instant
+const MIN_SPAN: Duration = Duration::from_secs(1);
+
+/// Throughput and progress of the queue, see `Downloader::get_stats`
+#[derive(Debug, Clone, Default)]
+pub struct DownloadStats {
+	/// Bytes per second of all downloads, over the last few seconds
+	pub speed: f64,
+	/// Bytes per second of every running download by ID
+	pub speeds: HashMap<i64, f64>,
+	/// Bytes received so far
+	pub bytes: u64,
+	/// Bytes left, using the average size for downloads which haven't started yet
+	pub remaining_bytes: Option<u64>,
+	/// Time left at the current speed
+	pub eta: Option<Duration>,
+	/// Time at least one download was receiving data
+	pub active_time: Duration,
+	/// Waiting in the queue
+	pub waiting: StageTime,
+	pub downloading: StageTime,
+	pub post_processing: StageTime,
+}
+
+impl DownloadStats {
+	/// Bytes per second while downloading
+	pub fn average_speed(&self) -> f64 {
+		match self.active_time.as_secs_f64() {
+			t if t > 0. => self.bytes as f64 / t,
+			_ => 0.,
+		}
+	}
+}
+
+/// Time downloads spent in a stage
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageTime {
+	/// Summed over all downloads
+	pub total: Duration,
+	/// Times a download finished the stage
+	pub count: u32,
+}
+
+impl StageTime {
+	/// Average time of a single download
+	pub fn average(&self) -> Duration {
+		match self.count {
+			0 => Duration::ZERO,
+			count => self.total / count,
+		}
+	}
+
+	fn add(&mut self, time: Duration) {
+		self.total += time;
+		self.count += 1;
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+	Waiting,
+	Downloading,
+	PostProcessing,
+	Finished,
+}
+
+impl From<&DownloadState> for Stage {
+	fn from(state: &DownloadState) -> Self {
+		match state {
+			DownloadState::None | DownloadState::Lock => Stage::Waiting,
+			DownloadState::Downloading(_, _) => Stage::Downloading,
+			DownloadState::Post => Stage::PostProcessing,
+			DownloadState::Done | DownloadState::Error(_) => Stage::Finished,
+		}
+	}
+}
+
+/// Bytes received within the last `WINDOW`
+#[derive(Debug, Default)]
+struct Window {
+	samples: VecDeque<(Instant, usize)>,
+	started: Option<Instant>,
+}
+
+impl Window {
+	fn add(&mut self, now: Instant, bytes: usize) {
+		self.started.get_or_insert(now);
+		self.samples.push_back((now, bytes));
+		while let Some((at, _)) = self.samples.front() {
+			if now.duration_since(*at) <= WINDOW {
+				break;
+			}
+			self.samples.pop_front();
+		}
+	}
+
+	/// Bytes per second
+	fn speed(&self, now: Instant) -> f64 {
+		let started = match self.started {
+			Some(started) => started,
+			None => return 0.,
+		};
+		// Shorter span until a full window was recorded
+		let span = now.duration_since(started).clamp(MIN_SPAN, WINDOW);
+		let bytes: usize = self
+			.samples
+			.iter()
+			.filter(|(at, _)| now.duration_since(*at) <= WINDOW)
+			.map(|(_, bytes)| bytes)
+			.sum();
+		bytes as f64 / span.as_secs_f64()
+	}
+}
+
+/// Statistics of a single download
+#[derive(Debug)]
+struct Tracked {
+	stage: Stage,
+	since: Instant,
+	read: usize,
+	size: Option<usize>,
+	window: Window,
+}
+
+impl Tracked {
+	fn new(now: Instant) -> Tracked {
+		Tracked {
+			stage: Stage::Waiting,
+			since: now,
+			read: 0,
+			size: None,
+			window: Window::default(),
+		}
+	}
+}
+
+/// Collects statistics from the state changes of the queue
+#[derive(Debug, Default)]
+pub(crate) struct StatsTracker {
+	downloads: HashMap<i64, Tracked>,
+	total: Window,
+	bytes: u64,
+	/// Downloads receiving data
+	active: usize,
+	active_since: Option<Instant>,
+	active_time: Duration,
+	waiting: StageTime,
+	downloading: StageTime,
+	post_processing: StageTime,
+}
+
+impl StatsTracker {
+	/// Download was added to the queue
+	pub fn queued(&mut self, id: i64) {
+		self.downloads.insert(id, Tracked::new(Instant::now()));
+	}
+
+	/// State of download changed
+	pub fn update(&mut self, id: i64, state: &DownloadState) {
+		let now = Instant::now();
+		let tracked = self
+			.downloads
+			.entry(id)
+			.or_insert_with(|| Tracked::new(now));
+
+		if let DownloadState::Downloading(read, size) = *state {
+			// Restarted by a retry
+			let bytes = match read.checked_sub(tracked.read) {
+				Some(bytes) => bytes,
+				None => read,
+			};
+			tracked.read = read;
+			tracked.size = Some(size);
+			tracked.window.add(now, bytes);
+			self.total.add(now, bytes);
+			self.bytes += bytes as u64;
+		}
+
+		let stage = Stage::from(state);
+		if stage == tracked.stage {
+			return;
+		}
+		let time = now.duration_since(tracked.since);
+		match tracked.stage {
+			Stage::Waiting => self.waiting.add(time),
+			Stage::Downloading => self.downloading.add(time),
+			Stage::PostProcessing => self.post_processing.add(time),
+			Stage::Finished => {}
+		}
+		if tracked.stage == Stage::Downloading {
+			self.active -= 1;
+			if self.active == 0 {
+				if let Some(since) = self.active_since.take() {
+					self.active_time += now.duration_since(since);
+				}
+			}
+		}
+		if stage == Stage::Downloading {
+			if self.active == 0 {
+				self.active_since = Some(now);
+			}
+			self.active += 1;
+		}
+		// Queued again
+		if stage == Stage::Waiting {
+			tracked.read = 0;
+			tracked.window = Window::default();
+		}
+		tracked.stage = stage;
+		tracked.since = now;
+	}
+
+	/// Current statistics
+	pub fn stats(&self) -> DownloadStats {
+		let now = Instant::now();
+		let speeds = self
+			.downloads
+			.iter()
+			.filter(|(_, t)| t.stage == Stage::Downloading)
+			.map(|(id, t)| (*id, t.window.speed(now)))
+			.collect();
+
+		// Downloads which haven't started yet are predicted to be of average size
+		let sizes: Vec<usize> = self.downloads.values().filter_map(|t| t.size).collect();
+		let average_size = match sizes.len() {
+			0 => None,
+			len => Some(sizes.iter().sum::<usize>() / len),
+		};
+		let mut remaining_bytes = Some(0u64);
+		for tracked in self.downloads.values() {
+			let left = match (tracked.stage, tracked.size) {
+				(Stage::PostProcessing | Stage::Finished, _) => Some(0),
+				(_, Some(size)) => Some(size.saturating_sub(tracked.read)),
+				(_, None) => average_size,
+			};
+			remaining_bytes = remaining_bytes
+				.zip(left)
+				.map(|(total, left)| total + left as u64);
+		}
+
+		let speed = self.total.speed(now);
+		let eta = match remaining_bytes {
+			Some(remaining) if speed > 0. => {
+				Some(Duration::from_secs_f64(remaining as f64 / speed))
+			}
+			_ => None,
+		};
+		let active_time = match self.active_since {
+			Some(since) => self.active_time + now.duration_since(since),
+			None => self.active_time,
+		};
+
+		DownloadStats {
+			speed,
+			speeds,
+			bytes: self.bytes,
+			remaining_bytes,
+			eta,
+			active_time,
+			waiting: self.waiting,
+			downloading: self.downloading,
+			post_processing: self.post_processing,
+		}
+	}
+}
@@ -1,12 +1,13 @@
 use chrono::{Datelike, NaiveDate};
-use id3::frame::{Picture, PictureType, Timestamp, UniqueFileIdentifier};
-use id3::{Tag, TagLike, Version};
+use id3::frame::{ExtendedText, Picture, PictureType, Timestamp, UniqueFileIdentifier};
+use id3::{Content, Tag, TagLike, Version};
 use std::path::{Path, PathBuf};
 
 use crate::error::SpotifyError;
 
 use super::Field;
 
+/// ID3 tag of an MP3 file
 pub struct ID3Tag {
 	path: PathBuf,
 	tag: Tag,
@@ -34,6 +35,18 @@ impl ID3Tag {
 			false => self.version = Version::Id3v23,
 		}
 	}
+
+	/// Set user defined text frame (TXXX)
+	fn set_extended_text(&mut self, description: &str, value: Vec<String>) {
+		let value = match self.version {
+			Version::Id3v24 => value.join("\0"),
+			_ => value.join(&self.separator),
+		};
+		self.tag.add_frame(ExtendedText {
+			description: description.to_string(),
+			value,
+		});
+	}
 }
 
 impl super::Tag for ID3Tag {
@@ -42,21 +55,43 @@ impl super::Tag for ID3Tag {
 	}
 
 	fn set_raw(&mut self, tag: &str, value: Vec<String>) {
-		self.tag.set_text(tag, value.join(&self.separator));
+		// ID3v2.4 supports null separated values natively
+		match self.version {
+			Version::Id3v24 => self.tag.set_text_values(tag, value),
+			_ => self.tag.set_text(tag, value.join(&self.separator)),
+		}
 	}
 
 	fn set_field(&mut self, field: Field, value: Vec<String>) {
-		let tag = match field {
-			Field::Title => "TIT2",
-			Field::Artist => "TPE1",
-			Field::Album => "TALB",
-			Field::TrackNumber => "TRCK",
-			Field::DiscNumber => "TPOS",
-			Field::Genre => "TCON",
-			Field::Label => "TPUB",
-			Field::AlbumArtist => "TPE2",
+		match field {
+			Field::Artists => self.set_extended_text("ARTISTS", value),
+			field => self.set_raw(frame_id(&field), value),
+		}
+	}
+
+	fn get_field(&self, field: Field) -> Vec<String> {
+		let text = match field {
+			Field::Artists => self
+				.tag
+				.extended_texts()
+				.find(|t| t.description == "ARTISTS")
+				.map(|t| t.value.as_str()),
+			field => self
+				.tag
+				.get(frame_id(&field))
+				.and_then(|f| f.content().text()),
 		};
-		self.set_raw(tag, value);
+		text.map(|t| t.split('\0').map(String::from).collect())
+			.unwrap_or_default()
+	}
+
+	fn set_field_joined(&mut self, field: Field, value: Vec<String>) {
+		let value = value.join(&self.separator);
+		self.set_field(field, vec![value]);
+	}
+
+	fn set_custom(&mut self, name: &str, value: Vec<String>) {
+		self.set_extended_text(name, value);
 	}
 
 	fn save(&mut self) -> Result<(), SpotifyError> {
@@ -72,6 +107,13 @@ impl super::Tag for ID3Tag {
 		});
 	}
 
+	fn get_cover(&self) -> Option<(String, Vec<u8>)> {
+		self.tag
+			.pictures()
+			.find(|p| p.picture_type == PictureType::CoverFront)
+			.map(|p| (p.mime_type.clone(), p.data.clone()))
+	}
+
 	fn set_release_date(&mut self, date: NaiveDate) {
 		self.tag.set_date_released(Timestamp {
 			year: date.year(),
@@ -83,10 +125,38 @@ impl super::Tag for ID3Tag {
 		})
 	}
 
+	fn get_release_date(&self) -> Option<String> {
+		self.tag.date_released().map(|date| date.to_string())
+	}
+
 	fn add_unique_file_identifier(&mut self, track_id: &str) {
 		self.tag.add_frame(UniqueFileIdentifier {
 			owner_identifier: "spotify.com".to_string(),
 			identifier: track_id.into(),
 		});
 	}
+
+	fn get_unique_file_identifier(&self) -> Option<String> {
+		self.tag.frames().find_map(|f| match f.content() {
+			Content::UniqueFileIdentifier(ufid) if ufid.owner_identifier == "spotify.com" => {
+				String::from_utf8(ufid.identifier.clone()).ok()
+			}
+			_ => None,
+		})
+	}
+}
+
+/// Get text frame ID of field
+fn frame_id(field: &Field) -> &'static str {
+	match field {
+		Field::Title => "TIT2",
+		Field::Artist => "TPE1",
+		Field::Artists => "TXXX",
+		Field::Album => "TALB",
+		Field::TrackNumber => "TRCK",
+		Field::DiscNumber => "TPOS",
+		Field::Genre => "TCON",
+		Field::Label => "TPUB",
+		Field::AlbumArtist => "TPE2",
+	}
 }
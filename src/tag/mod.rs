@@ -5,12 +5,13 @@ use std::path::Path;
 use crate::downloader::AudioFormat;
 use crate::error::SpotifyError;
 
-use self::id3::ID3Tag;
-use ogg::OggTag;
+pub use self::id3::ID3Tag;
+pub use ogg::OggTag;
 
 mod id3;
 mod ogg;
 
+/// Tags of an Ogg or MP3 file
 pub enum TagWrap {
 	Ogg(OggTag),
 	Id3(ID3Tag),
@@ -33,24 +34,50 @@ impl TagWrap {
 			TagWrap::Id3(tag) => tag,
 		}
 	}
+
+	/// Get Tag trait for reading
+	pub fn tag(&self) -> &dyn Tag {
+		match self {
+			TagWrap::Ogg(tag) => tag,
+			TagWrap::Id3(tag) => tag,
+		}
+	}
 }
 
+/// Reading and writing tags, independent of the file format
 pub trait Tag {
 	/// Set tag values separator
 	fn set_separator(&mut self, separator: &str);
+	/// Set tag by its format specific name
 	fn set_raw(&mut self, tag: &str, value: Vec<String>);
 	fn set_field(&mut self, field: Field, value: Vec<String>);
+	fn get_field(&self, field: Field) -> Vec<String>;
+	/// Set field as a single value joined with the separator
+	fn set_field_joined(&mut self, field: Field, value: Vec<String>);
+	/// Set user defined tag (Vorbis comment / `TXXX` frame)
+	fn set_custom(&mut self, name: &str, value: Vec<String>);
 	fn set_release_date(&mut self, date: NaiveDate);
+	/// Get the release date as `YYYY-MM-DD`
+	fn get_release_date(&self) -> Option<String>;
+	/// Add front cover image
 	fn add_cover(&mut self, mime: &str, data: Vec<u8>);
+	/// Get mime and data of the front cover image
+	fn get_cover(&self) -> Option<(String, Vec<u8>)>;
 	/// Adds the file identifier of the track
 	fn add_unique_file_identifier(&mut self, track_id: &str);
+	/// Get the Spotify track ID written by `add_unique_file_identifier`
+	fn get_unique_file_identifier(&self) -> Option<String>;
+	/// Write changes to file
 	fn save(&mut self) -> Result<(), SpotifyError>;
 }
 
+/// Tag fields with a name in both formats
 #[derive(Debug, Clone)]
 pub enum Field {
 	Title,
 	Artist,
+	/// Multi-valued list of all artists (`ARTISTS` / `TXXX:ARTISTS`)
+	Artists,
 	Album,
 	TrackNumber,
 	DiscNumber,
@@ -58,3 +85,20 @@ pub enum Field {
 	Genre,
 	Label,
 }
+
+impl Field {
+	/// All fields
+	pub fn all() -> Vec<Field> {
+		vec![
+			Field::Title,
+			Field::Artist,
+			Field::Artists,
+			Field::Album,
+			Field::TrackNumber,
+			Field::DiscNumber,
+			Field::AlbumArtist,
+			Field::Genre,
+			Field::Label,
+		]
+	}
+}
@@ -8,9 +8,11 @@ use std::path::{Path, PathBuf};
 use super::Field;
 use crate::error::SpotifyError;
 
+/// Vorbis comments of an Ogg file
 pub struct OggTag {
 	path: PathBuf,
 	tag: CommentHeader,
+	separator: String,
 }
 
 impl OggTag {
@@ -21,25 +23,27 @@ impl OggTag {
 		Ok(OggTag {
 			path: path.as_ref().to_owned(),
 			tag,
+			separator: String::new(),
 		})
 	}
 }
 
 impl super::Tag for OggTag {
-	fn set_separator(&mut self, _separator: &str) {}
+	fn set_separator(&mut self, separator: &str) {
+		self.separator = separator.to_string();
+	}
 
 	fn set_field(&mut self, field: Field, value: Vec<String>) {
-		let tag = match field {
-			Field::Title => "TITLE",
-			Field::Artist => "ARTIST",
-			Field::Album => "ALBUM",
-			Field::TrackNumber => "TRACKNUMBER",
-			Field::DiscNumber => "DISCNUMBER",
-			Field::Genre => "GENRE",
-			Field::Label => "LABEL",
-			Field::AlbumArtist => "ALBUMARTIST",
-		};
-		self.set_raw(tag, value);
+		self.set_raw(comment_name(&field), value);
+	}
+
+	fn get_field(&self, field: Field) -> Vec<String> {
+		self.tag.get_tag_multi(comment_name(&field))
+	}
+
+	fn set_field_joined(&mut self, field: Field, value: Vec<String>) {
+		let value = value.join(&self.separator);
+		self.set_field(field, vec![value]);
 	}
 
 	fn add_cover(&mut self, mime: &str, data: Vec<u8>) {
@@ -63,19 +67,30 @@ impl super::Tag for OggTag {
 		picture.extend((data.len() as u32).to_be_bytes().iter());
 		picture.extend(data);
 
+		self.tag.clear_tag("METADATA_BLOCK_PICTURE");
 		self.tag.add_tag_single(
 			"METADATA_BLOCK_PICTURE",
 			&general_purpose::STANDARD.encode(picture),
 		);
 	}
 
+	fn get_cover(&self) -> Option<(String, Vec<u8>)> {
+		let picture = self.tag.get_tag_single("METADATA_BLOCK_PICTURE")?;
+		parse_picture(&general_purpose::STANDARD.decode(picture).ok()?)
+	}
+
 	fn set_raw(&mut self, tag: &str, value: Vec<String>) {
+		self.tag.clear_tag(tag);
 		self.tag.add_tag_multi(
 			tag,
 			&value.iter().map(|v| v.as_str()).collect::<Vec<&str>>(),
 		);
 	}
 
+	fn set_custom(&mut self, name: &str, value: Vec<String>) {
+		self.set_raw(name, value);
+	}
+
 	fn save(&mut self) -> Result<(), SpotifyError> {
 		let file = File::open(&self.path)?;
 		let mut out = replace_comment_header(file, self.tag.clone());
@@ -85,13 +100,59 @@ impl super::Tag for OggTag {
 	}
 
 	fn set_release_date(&mut self, date: NaiveDate) {
+		self.tag.clear_tag("DATE");
 		self.tag.add_tag_single(
 			"DATE",
 			&format!("{}-{:02}-{:02}", date.year(), date.month(), date.day()),
 		)
 	}
 
+	fn get_release_date(&self) -> Option<String> {
+		self.tag.get_tag_single("DATE")
+	}
+
 	fn add_unique_file_identifier(&mut self, track_id: &str) {
+		self.tag.clear_tag("SPOTIFY_TRACKID");
 		self.tag.add_tag_single("SPOTIFY_TRACKID", track_id);
 	}
+
+	fn get_unique_file_identifier(&self) -> Option<String> {
+		self.tag.get_tag_single("SPOTIFY_TRACKID")
+	}
+}
+
+/// Get mime and image data of a FLAC picture block, as written by `add_cover`
+fn parse_picture(picture: &[u8]) -> Option<(String, Vec<u8>)> {
+	let length = |pos: usize| -> Option<usize> {
+		let bytes = picture.get(pos..pos + 4)?;
+		Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize)
+	};
+
+	// Skip picture type
+	let mut pos = 4;
+	let mime_length = length(pos)?;
+	pos += 4;
+	let mime = String::from_utf8(picture.get(pos..pos + mime_length)?.to_vec()).ok()?;
+	pos += mime_length;
+	// Skip description, width, height, depth, and number of colors
+	pos += 4 + length(pos)? + 16;
+	let data_length = length(pos)?;
+	pos += 4;
+	let data = picture.get(pos..pos + data_length)?.to_vec();
+	Some((mime, data))
+}
+
+/// Get vorbis comment name of field
+fn comment_name(field: &Field) -> &'static str {
+	match field {
+		Field::Title => "TITLE",
+		Field::Artist => "ARTIST",
+		Field::Artists => "ARTISTS",
+		Field::Album => "ALBUM",
+		Field::TrackNumber => "TRACKNUMBER",
+		Field::DiscNumber => "DISCNUMBER",
+		Field::Genre => "GENRE",
+		Field::Label => "LABEL",
+		Field::AlbumArtist => "ALBUMARTIST",
+	}
 }
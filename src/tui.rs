@@ -0,0 +1,372 @@
+use down_on_spot::{
+	AudioFormat, Download, DownloadEvent, DownloadState, DownloadStats, Downloader, SpotifyError,
+};
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use std::io;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::output::{self, format_bytes, secs_to_hrs_min_sec, Summary};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Characters of a progress bar
+const BAR_WIDTH: usize = 20;
+/// Redraw at least this often, also without input
+const MIN_REFRESH: Duration = Duration::from_millis(100);
+
+/// Which downloads are listed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Filter {
+	All,
+	Active,
+	Queued,
+	Done,
+	Failed,
+}
+
+impl Filter {
+	fn next(self) -> Filter {
+		match self {
+			Filter::All => Filter::Active,
+			Filter::Active => Filter::Queued,
+			Filter::Queued => Filter::Done,
+			Filter::Done => Filter::Failed,
+			Filter::Failed => Filter::All,
+		}
+	}
+
+	fn name(self) -> &'static str {
+		match self {
+			Filter::All => "all",
+			Filter::Active => "active",
+			Filter::Queued => "queued",
+			Filter::Done => "done",
+			Filter::Failed => "failed",
+		}
+	}
+
+	fn matches(self, download: &Download) -> bool {
+		match (self, &download.state) {
+			(Filter::All, _) => true,
+			(
+				Filter::Active,
+				DownloadState::Lock | DownloadState::Downloading(_, _) | DownloadState::Post,
+			) => true,
+			(Filter::Queued, DownloadState::None) => true,
+			(Filter::Done, DownloadState::Done) => true,
+			(Filter::Done, DownloadState::Error(SpotifyError::AlreadyDownloaded)) => true,
+			(Filter::Failed, DownloadState::Error(e)) => e != &SpotifyError::AlreadyDownloaded,
+			_ => false,
+		}
+	}
+}
+
+struct App {
+	downloads: Vec<Download>,
+	stats: DownloadStats,
+	filter: Filter,
+	list: ListState,
+	start: Instant,
+}
+
+impl App {
+	fn new() -> App {
+		App {
+			downloads: vec![],
+			stats: DownloadStats::default(),
+			filter: Filter::All,
+			list: ListState::default(),
+			start: Instant::now(),
+		}
+	}
+
+	/// Update downloads with event
+	fn apply(&mut self, event: DownloadEvent) {
+		output::apply_event(&mut self.downloads, event);
+	}
+
+	/// Speed of a running download
+	fn speed(&self, download: &Download) -> Option<f64> {
+		self.stats.speeds.get(&download.id).copied()
+	}
+
+	fn visible(&self) -> Vec<&Download> {
+		self.downloads
+			.iter()
+			.filter(|d| self.filter.matches(d))
+			.collect()
+	}
+
+	fn selected(&self) -> Option<&Download> {
+		self.visible().get(self.list.selected()?).copied()
+	}
+
+	/// Move selection by offset, clamped to the list
+	fn select(&mut self, offset: isize) {
+		let len = self.visible().len();
+		if len == 0 {
+			self.list.select(None);
+			return;
+		}
+		let current = self.list.selected().unwrap_or(0) as isize;
+		let i = (current + offset).clamp(0, len as isize - 1);
+		self.list.select(Some(i as usize));
+	}
+
+	/// Move download in front of the other waiting ones, like the downloader does
+	fn prioritize(&mut self, id: i64) {
+		let i = match self
+			.downloads
+			.iter()
+			.position(|d| d.id == id && d.state == DownloadState::None)
+		{
+			Some(i) => i,
+			None => return,
+		};
+		let download = self.downloads.remove(i);
+		let first = self
+			.downloads
+			.iter()
+			.position(|d| d.state == DownloadState::None)
+			.unwrap_or(self.downloads.len());
+		self.downloads.insert(first, download);
+	}
+
+	fn draw(&mut self, frame: &mut Frame) {
+		let areas = Layout::vertical([
+			Constraint::Length(1),
+			Constraint::Min(3),
+			Constraint::Length(8),
+			Constraint::Length(1),
+		])
+		.split(frame.area());
+
+		// Keep selection within the filtered list
+		self.select(0);
+
+		frame.render_widget(Paragraph::new(self.header()), areas[0]);
+
+		let items: Vec<ListItem> = self
+			.visible()
+			.into_iter()
+			.map(|d| ListItem::new(self.row(d)).style(state_style(&d.state)))
+			.collect();
+		let list = List::new(items)
+			.block(Block::bordered().title(format!(" Queue ({}) ", self.filter.name())))
+			.highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+		frame.render_stateful_widget(list, areas[1], &mut self.list);
+
+		let details = Paragraph::new(self.details()).block(Block::bordered().title(" Details "));
+		frame.render_widget(details, areas[2]);
+
+		frame.render_widget(
+			Paragraph::new(
+				" ↑/↓ select  PgUp/PgDn scroll  f filter  c cancel  r retry  p prioritize  q quit",
+			)
+			.style(Style::new().add_modifier(Modifier::DIM)),
+			areas[3],
+		);
+	}
+
+	/// Totals, overall speed and remaining time
+	fn header(&self) -> Line<'static> {
+		let summary = Summary::new(&self.downloads, self.start);
+		let active = self
+			.downloads
+			.iter()
+			.filter(|d| Filter::Active.matches(d))
+			.count();
+		let eta = match self.stats.eta {
+			Some(eta) => secs_to_hrs_min_sec(eta.as_secs()),
+			None => "--:--:--".to_string(),
+		};
+		Line::from(format!(
+			" DownOnSpot v{} | {} | {}/{} done, {} skipped, {} failed | {} active, {}/s, {} left",
+			VERSION,
+			secs_to_hrs_min_sec(summary.elapsed_secs),
+			summary.done,
+			summary.total,
+			summary.skipped,
+			summary.failed,
+			active,
+			format_bytes(self.stats.speed),
+			eta
+		))
+		.style(Style::new().add_modifier(Modifier::BOLD))
+	}
+
+	/// State, progress bar and title
+	fn row(&self, download: &Download) -> String {
+		let progress = match download.state {
+			DownloadState::Downloading(read, total) => {
+				let p = match total {
+					0 => 0.,
+					total => (read as f64 / total as f64).min(1.),
+				};
+				let filled = (p * BAR_WIDTH as f64).round() as usize;
+				format!(
+					"[{}{}] {: >3}% {: >9}/s",
+					"#".repeat(filled),
+					".".repeat(BAR_WIDTH - filled),
+					(p * 100.) as u8,
+					format_bytes(self.speed(download).unwrap_or(0.))
+				)
+			}
+			_ => String::new(),
+		};
+		format!(
+			"{: <15} {: <40} {}",
+			output::state_name(&download.state),
+			progress,
+			download.title
+		)
+	}
+
+	/// Error, path and format of the selected download
+	fn details(&self) -> Vec<Line<'static>> {
+		let download = match self.selected() {
+			Some(download) => download,
+			None => return vec![Line::from("Nothing selected")],
+		};
+		let state = match &download.state {
+			DownloadState::Downloading(read, total) => format!(
+				"downloading, {} of {} at {}/s",
+				format_bytes(*read as f64),
+				format_bytes(*total as f64),
+				format_bytes(self.speed(download).unwrap_or(0.))
+			),
+			state => output::state_name(state).to_string(),
+		};
+		let format = match &download.path {
+			Some(path) => format!("{:?}", AudioFormat::from_path(path)),
+			None => "-".to_string(),
+		};
+		let path = match &download.path {
+			Some(path) => path.to_string_lossy().to_string(),
+			None => "-".to_string(),
+		};
+		let error = match &download.state {
+			DownloadState::Error(e) => format!("{} ({})", e, e.kind()),
+			_ => "-".to_string(),
+		};
+		vec![
+			Line::from(format!("Title:  {}", download.title)),
+			Line::from(format!("Track:  spotify:track:{}", download.track_id)),
+			Line::from(format!("State:  {}", state)),
+			Line::from(format!("Format: {}", format)),
+			Line::from(format!("Path:   {}", path)),
+			Line::from(format!("Error:  {}", error)),
+		]
+	}
+}
+
+/// Run the terminal UI until the user quits
+///
+/// `events` has to be subscribed before anything was added to the queue.
+pub async fn run(
+	downloader: &Downloader,
+	mut events: BoxStream<'static, DownloadEvent>,
+	refresh: Duration,
+) -> io::Result<Summary> {
+	// Reading keys blocks
+	let (keys_tx, mut keys) = mpsc::channel(16);
+	std::thread::spawn(move || loop {
+		match event::read() {
+			Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+				if keys_tx.blocking_send(key).is_err() {
+					break;
+				}
+			}
+			Ok(_) => {}
+			Err(_) => break,
+		}
+	});
+
+	// All queued downloads have been announced once this returns
+	let queued = downloader.get_downloads().await.len();
+	let mut terminal = ratatui::init();
+	let mut app = App::new();
+	let refresh = refresh.max(MIN_REFRESH);
+	let result = loop {
+		while let Some(Some(event)) = events.next().now_or_never() {
+			match event {
+				DownloadEvent::Lagged(_) => app.downloads = downloader.get_downloads().await,
+				event => app.apply(event),
+			}
+		}
+		app.stats = downloader.get_stats().await;
+		if let Err(e) = terminal.draw(|frame| app.draw(frame)) {
+			break Err(e);
+		}
+		if app.downloads.len() >= queued && app.downloads.iter().all(output::is_finished) {
+			break Ok(());
+		}
+
+		let key = match async_std::future::timeout(refresh, keys.recv()).await {
+			Ok(Some(key)) => key,
+			// Input is gone
+			Ok(None) => break Ok(()),
+			Err(_) => continue,
+		};
+		if handle_key(&mut app, downloader, key).await {
+			break Ok(());
+		}
+	};
+	ratatui::restore();
+
+	result.map(|_| Summary::new(&app.downloads, app.start))
+}
+
+/// Handle key press, returns whether to quit
+async fn handle_key(app: &mut App, downloader: &Downloader, key: KeyEvent) -> bool {
+	let selected = app.selected().map(|d| d.id);
+	match key.code {
+		KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return true,
+		KeyCode::Char('q') | KeyCode::Esc => return true,
+		KeyCode::Up | KeyCode::Char('k') => app.select(-1),
+		KeyCode::Down | KeyCode::Char('j') => app.select(1),
+		KeyCode::PageUp => app.select(-10),
+		KeyCode::PageDown => app.select(10),
+		KeyCode::Home => app.select(isize::MIN / 2),
+		KeyCode::End => app.select(isize::MAX / 2),
+		KeyCode::Char('f') => {
+			app.filter = app.filter.next();
+			app.list.select(Some(0));
+		}
+		KeyCode::Char('c') => {
+			if let Some(id) = selected {
+				downloader.cancel(id).await;
+			}
+		}
+		KeyCode::Char('r') => {
+			if let Some(id) = selected {
+				downloader.retry(id).await;
+			}
+		}
+		KeyCode::Char('p') => {
+			if let Some(id) = selected {
+				downloader.prioritize(id).await;
+				app.prioritize(id);
+			}
+		}
+		_ => {}
+	}
+	false
+}
+
+fn state_style(state: &DownloadState) -> Style {
+	match state {
+		DownloadState::Done => Style::new().fg(Color::Green),
+		DownloadState::Error(SpotifyError::AlreadyDownloaded) => Style::new().fg(Color::Yellow),
+		DownloadState::Error(_) => Style::new().fg(Color::Red),
+		DownloadState::Downloading(_, _) | DownloadState::Post => Style::new().fg(Color::Cyan),
+		DownloadState::None | DownloadState::Lock => Style::new(),
+	}
+}
@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::decoder::decode_file_limit;
+use crate::downloader::AudioFormat;
+use crate::error::SpotifyError;
+use crate::error::SpotifyError::VerifyError;
+
+/// Amount of audio decoded in `VerifyMode::Sample`
+const SAMPLE_DURATION: Duration = Duration::from_secs(10);
+/// Maximum difference of decoded audio and the track duration in metadata
+pub(crate) const DURATION_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// How thoroughly downloaded files are checked
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum VerifyMode {
+	#[default]
+	Off,
+	/// Parse the container only (Ogg page CRCs, MP3 frame sync)
+	Container,
+	/// Parse the container and decode the beginning
+	Sample,
+	/// Parse the container, decode everything and compare the length to the track duration
+	Full,
+}
+
+/// Check that a downloaded file is playable ( BLOCKING )
+pub fn verify_file(
+	path: impl AsRef<Path>,
+	format: AudioFormat,
+	mode: VerifyMode,
+	expected: Duration,
+) -> Result<(), SpotifyError> {
+	if mode == VerifyMode::Off {
+		return Ok(());
+	}
+
+	let data = std::fs::read(&path)?;
+	match format {
+		AudioFormat::Ogg => verify_ogg(&data)?,
+		AudioFormat::Mp3 => verify_mp3(&data)?,
+		_ => return Ok(()),
+	}
+
+	let limit = match mode {
+		VerifyMode::Off | VerifyMode::Container => return Ok(()),
+		VerifyMode::Sample => Some(SAMPLE_DURATION),
+		VerifyMode::Full => None,
+	};
+	let mut seconds = 0.0;
+	decode_file_limit(&path, format, limit, |packet, sample_rate| {
+		seconds += packet.first().map(|c| c.len()).unwrap_or(0) as f64 / sample_rate as f64;
+	})
+	.map_err(|e| VerifyError(format!("Decoding failed: {}", e)))?;
+
+	if seconds == 0.0 {
+		return Err(VerifyError("No audio".to_string()));
+	}
+	if mode == VerifyMode::Full {
+		let decoded = Duration::from_secs_f64(seconds);
+		if decoded + DURATION_TOLERANCE < expected || decoded > expected + DURATION_TOLERANCE {
+			return Err(VerifyError(format!(
+				"Decoded {}ms, expected {}ms",
+				decoded.as_millis(),
+				expected.as_millis()
+			)));
+		}
+	}
+	Ok(())
+}
+
+/// Walk all Ogg pages and check their CRCs, sequence numbers and the end of stream flag
+fn verify_ogg(data: &[u8]) -> Result<(), SpotifyError> {
+	let table = crc_table();
+	let mut offset = 0;
+	let mut sequence: Option<u32> = None;
+	let mut end_of_stream = false;
+
+	while offset < data.len() {
+		let page = &data[offset..];
+		if page.len() < 27 || &page[0..4] != b"OggS" {
+			return Err(VerifyError(format!("Lost Ogg sync at {}", offset)));
+		}
+		if end_of_stream {
+			return Err(VerifyError("Data after end of stream".to_string()));
+		}
+		let segments = page[26] as usize;
+		let header_size = 27 + segments;
+		if page.len() < header_size {
+			return Err(VerifyError(format!("Truncated Ogg page at {}", offset)));
+		}
+		let body_size: usize = page[27..header_size].iter().map(|s| *s as usize).sum();
+		let page_size = header_size + body_size;
+		if page.len() < page_size {
+			return Err(VerifyError(format!("Truncated Ogg page at {}", offset)));
+		}
+
+		let crc = u32::from_le_bytes([page[22], page[23], page[24], page[25]]);
+		if ogg_crc(&table, &page[..page_size]) != crc {
+			return Err(VerifyError(format!("Ogg CRC mismatch at {}", offset)));
+		}
+		let page_sequence = u32::from_le_bytes([page[18], page[19], page[20], page[21]]);
+		if let Some(previous) = sequence {
+			if page_sequence != previous.wrapping_add(1) {
+				return Err(VerifyError(format!("Missing Ogg page before {}", offset)));
+			}
+		}
+		sequence = Some(page_sequence);
+		end_of_stream = page[5] & 0x04 != 0;
+		offset += page_size;
+	}
+
+	if sequence.is_none() {
+		return Err(VerifyError("No Ogg pages".to_string()));
+	}
+	if !end_of_stream {
+		return Err(VerifyError("Missing Ogg end of stream".to_string()));
+	}
+	Ok(())
+}
+
+/// CRC-32 lookup table of Ogg (polynomial 0x04c11db7, not reflected)
+fn crc_table() -> [u32; 256] {
+	let mut table = [0u32; 256];
+	for (i, entry) in table.iter_mut().enumerate() {
+		let mut crc = (i as u32) << 24;
+		for _ in 0..8 {
+			crc = match crc & 0x80000000 {
+				0 => crc << 1,
+				_ => (crc << 1) ^ 0x04c11db7,
+			};
+		}
+		*entry = crc;
+	}
+	table
+}
+
+/// CRC of page with the checksum field zeroed
+fn ogg_crc(table: &[u32; 256], page: &[u8]) -> u32 {
+	page.iter().enumerate().fold(0u32, |crc, (i, byte)| {
+		let byte = match i {
+			22..=25 => 0,
+			_ => *byte,
+		};
+		(crc << 8) ^ table[((crc >> 24) as u8 ^ byte) as usize]
+	})
+}
+
+/// Walk all MP3 frames and check they are continuous
+fn verify_mp3(data: &[u8]) -> Result<(), SpotifyError> {
+	let mut offset = 0;
+	// ID3v2 tag
+	if data.len() >= 10 && &data[0..3] == b"ID3" {
+		let size = data[6..10]
+			.iter()
+			.fold(0usize, |size, b| (size << 7) | (*b & 0x7f) as usize);
+		let footer = match data[5] & 0x10 {
+			0 => 0,
+			_ => 10,
+		};
+		offset = 10 + size + footer;
+	}
+	// ID3v1 tag
+	let mut end = data.len();
+	if end >= 128 && &data[end - 128..end - 125] == b"TAG" {
+		end -= 128;
+	}
+
+	let mut frames = 0;
+	while offset < end {
+		let length = mp3_frame_length(&data[offset..end])
+			.ok_or_else(|| VerifyError(format!("Lost MP3 frame sync at {}", offset)))?;
+		if offset + length > end {
+			return Err(VerifyError(format!("Truncated MP3 frame at {}", offset)));
+		}
+		offset += length;
+		frames += 1;
+	}
+
+	if frames == 0 {
+		return Err(VerifyError("No MP3 frames".to_string()));
+	}
+	Ok(())
+}
+
+/// Length of the Layer III frame starting at data
+fn mp3_frame_length(data: &[u8]) -> Option<usize> {
+	if data.len() < 4 || data[0] != 0xff || data[1] & 0xe0 != 0xe0 {
+		return None;
+	}
+	// 3 = MPEG-1, 2 = MPEG-2, 0 = MPEG-2.5
+	let version = (data[1] >> 3) & 0x03;
+	let layer = (data[1] >> 1) & 0x03;
+	let bitrate_index = (data[2] >> 4) as usize;
+	let sample_rate_index = ((data[2] >> 2) & 0x03) as usize;
+	let padding = ((data[2] >> 1) & 0x01) as usize;
+	if version == 1 || layer != 1 || bitrate_index == 0 || bitrate_index == 15 {
+		return None;
+	}
+
+	let (bitrates, sample_rates, samples): ([usize; 15], [usize; 3], usize) = match version {
+		3 => (
+			[
+				0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+			],
+			[44100, 48000, 32000],
+			1152,
+		),
+		_ => (
+			[0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+			match version {
+				2 => [22050, 24000, 16000],
+				_ => [11025, 12000, 8000],
+			},
+			576,
+		),
+	};
+	let sample_rate = *sample_rates.get(sample_rate_index)?;
+	Some(samples / 8 * bitrates[bitrate_index] * 1000 / sample_rate + padding)
+}
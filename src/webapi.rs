@@ -0,0 +1,321 @@
+use aspotify::Market;
+use async_std::sync::Mutex;
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::SpotifyError;
+use crate::ratelimit::RateLimiter;
+
+const API_URL: &str = "https://api.spotify.com/v1";
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+/// Tokens are renewed this long before they expire
+const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+/// Web API client which sends every request through the rate limiter,
+/// so the Retry-After of a 429 response pauses all requests
+#[derive(Debug, Clone)]
+pub struct WebApi {
+	http: reqwest::Client,
+	api_url: String,
+	token_url: String,
+	client_id: String,
+	client_secret: String,
+	/// Shared by all clones, so the token is only fetched once
+	token: Arc<Mutex<Option<Token>>>,
+	limiter: RateLimiter,
+}
+
+#[derive(Debug)]
+struct Token {
+	value: String,
+	expires: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+	access_token: String,
+	expires_in: u64,
+}
+
+/// Paged results of the Web API
+#[derive(Debug, Deserialize)]
+pub struct Page<T> {
+	pub items: Vec<T>,
+	pub total: usize,
+}
+
+impl WebApi {
+	/// Create new instance
+	pub fn new(client_id: &str, client_secret: &str, limiter: RateLimiter) -> WebApi {
+		WebApi::with_urls(API_URL, TOKEN_URL, client_id, client_secret, limiter)
+	}
+
+	/// Create new instance using other endpoints, e.g. a mock server
+	pub fn with_urls(
+		api_url: &str,
+		token_url: &str,
+		client_id: &str,
+		client_secret: &str,
+		limiter: RateLimiter,
+	) -> WebApi {
+		WebApi {
+			http: reqwest::Client::new(),
+			api_url: api_url.trim_end_matches('/').to_string(),
+			token_url: token_url.to_string(),
+			client_id: client_id.to_string(),
+			client_secret: client_secret.to_string(),
+			token: Arc::new(Mutex::new(None)),
+			limiter,
+		}
+	}
+
+	/// GET endpoint and parse the JSON response
+	pub async fn get<T: DeserializeOwned>(
+		&self,
+		path: &str,
+		query: &[(&str, String)],
+	) -> Result<T, SpotifyError> {
+		let token = self.token().await?;
+		let request = self
+			.http
+			.get(format!("{}{}", self.api_url, path))
+			.bearer_auth(token)
+			.query(query);
+		let response = self.limiter.send(request).await?;
+		let status = response.status();
+		let body = response.text().await?;
+		// Malformed or unknown IDs
+		if status == StatusCode::BAD_REQUEST || status == StatusCode::NOT_FOUND {
+			debug!("{} {}: {}", path, status, body);
+			return Err(SpotifyError::Unavailable);
+		}
+		if !status.is_success() {
+			return Err(SpotifyError::ASpotify(format!("{} {}", status, body)));
+		}
+		Ok(serde_json::from_str(&body)?)
+	}
+
+	/// GET every page of endpoint, `limit` items at a time
+	pub async fn get_all<T: DeserializeOwned>(
+		&self,
+		path: &str,
+		query: &[(&str, String)],
+		limit: usize,
+	) -> Result<Vec<T>, SpotifyError> {
+		collect_pages(limit, |offset, limit| {
+			let mut page_query = query.to_vec();
+			page_query.push(("limit", limit.to_string()));
+			page_query.push(("offset", offset.to_string()));
+			async move { self.get(path, &page_query).await }
+		})
+		.await
+	}
+
+	/// Client credentials access token, fetched again when it expires
+	async fn token(&self) -> Result<String, SpotifyError> {
+		let mut token = self.token.lock().await;
+		if let Some(token) = &*token {
+			if token.expires > Instant::now() + TOKEN_MARGIN {
+				return Ok(token.value.clone());
+			}
+		}
+
+		let request = self
+			.http
+			.post(&self.token_url)
+			.basic_auth(&self.client_id, Some(&self.client_secret))
+			.form(&[("grant_type", "client_credentials")]);
+		let response = self.limiter.send(request).await?;
+		let status = response.status();
+		let body = response.text().await?;
+		if !status.is_success() {
+			return Err(SpotifyError::ASpotify(format!("{} {}", status, body)));
+		}
+		let response: TokenResponse = serde_json::from_str(&body)?;
+		let value = response.access_token;
+		*token = Some(Token {
+			value: value.clone(),
+			expires: Instant::now() + Duration::from_secs(response.expires_in),
+		});
+		Ok(value)
+	}
+}
+
+/// Fetch pages `limit` items at a time until all `total` items are collected
+pub(crate) async fn collect_pages<T, F, Fut>(
+	limit: usize,
+	mut fetch: F,
+) -> Result<Vec<T>, SpotifyError>
+where
+	F: FnMut(usize, usize) -> Fut,
+	Fut: Future<Output = Result<Page<T>, SpotifyError>>,
+{
+	let mut items = vec![];
+	loop {
+		let mut page = fetch(items.len(), limit).await?;
+
+		// End, or the total changed while paging
+		if page.items.is_empty() {
+			return Ok(items);
+		}
+		items.append(&mut page.items);
+		if items.len() >= page.total {
+			return Ok(items);
+		}
+	}
+}
+
+/// Query parameter of market
+pub fn market_query(market: Option<Market>) -> Vec<(&'static str, String)> {
+	match market {
+		Some(Market::Country(country)) => vec![("market", country.alpha2().to_string())],
+		Some(Market::FromToken) => vec![("market", "from_token".to_string())],
+		None => vec![],
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use wiremock::matchers::{method, path, query_param};
+	use wiremock::{Mock, MockServer, ResponseTemplate};
+
+	#[derive(Debug, Deserialize)]
+	struct Item {
+		id: String,
+	}
+
+	/// Token endpoint of the mock server
+	async fn mock_token(server: &MockServer) {
+		Mock::given(method("POST"))
+			.and(path("/token"))
+			.respond_with(ResponseTemplate::new(200).set_body_string(
+				r#"{"access_token":"token","token_type":"bearer","expires_in":3600}"#,
+			))
+			.mount(server)
+			.await;
+	}
+
+	/// Client of the mock server, retrying once
+	fn api(server: &MockServer) -> WebApi {
+		WebApi::with_urls(
+			&format!("{}/v1", server.uri()),
+			&format!("{}/token", server.uri()),
+			"id",
+			"secret",
+			RateLimiter::new(1, 1),
+		)
+	}
+
+	#[tokio::test]
+	async fn waits_for_retry_after() {
+		let server = MockServer::start().await;
+		mock_token(&server).await;
+		// First request is rate limited, the retry succeeds
+		Mock::given(method("GET"))
+			.and(path("/v1/items/1"))
+			.respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "2"))
+			.up_to_n_times(1)
+			.expect(1)
+			.mount(&server)
+			.await;
+		Mock::given(method("GET"))
+			.and(path("/v1/items/1"))
+			.respond_with(ResponseTemplate::new(200).set_body_string(r#"{"id":"1"}"#))
+			.expect(1)
+			.mount(&server)
+			.await;
+
+		let api = api(&server);
+		let start = Instant::now();
+		let item: Item = api.get("/items/1", &[]).await.unwrap();
+		assert_eq!(item.id, "1");
+		let elapsed = start.elapsed();
+		assert!(elapsed >= Duration::from_secs(2), "waited {:?}", elapsed);
+		assert!(elapsed < Duration::from_secs(4), "waited {:?}", elapsed);
+	}
+
+	#[tokio::test]
+	async fn gives_up_after_max_retries() {
+		let server = MockServer::start().await;
+		mock_token(&server).await;
+		Mock::given(method("GET"))
+			.and(path("/v1/items/1"))
+			.respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+			.expect(2)
+			.mount(&server)
+			.await;
+
+		let api = api(&server);
+		let result: Result<Item, _> = api.get("/items/1", &[]).await;
+		assert_eq!(result.unwrap_err(), SpotifyError::RateLimited(Some(1)));
+	}
+
+	#[tokio::test]
+	async fn unknown_id_is_unavailable() {
+		let server = MockServer::start().await;
+		mock_token(&server).await;
+		Mock::given(method("GET"))
+			.and(path("/v1/tracks/missing"))
+			.respond_with(
+				ResponseTemplate::new(404)
+					.set_body_string(r#"{"error":{"status":404,"message":"Non existing id"}}"#),
+			)
+			.mount(&server)
+			.await;
+		Mock::given(method("GET"))
+			.and(path("/v1/tracks/invalid"))
+			.respond_with(
+				ResponseTemplate::new(400)
+					.set_body_string(r#"{"error":{"status":400,"message":"invalid id"}}"#),
+			)
+			.mount(&server)
+			.await;
+
+		let api = api(&server);
+		for path in ["/tracks/missing", "/tracks/invalid"] {
+			let result: Result<Item, _> = api.get(path, &[]).await;
+			assert_eq!(result.unwrap_err(), SpotifyError::Unavailable);
+		}
+		let result: Result<Vec<Item>, _> = api.get_all("/tracks/missing", &[], 50).await;
+		assert_eq!(result.unwrap_err(), SpotifyError::Unavailable);
+	}
+
+	#[tokio::test]
+	async fn fetches_all_pages() {
+		let server = MockServer::start().await;
+		mock_token(&server).await;
+		Mock::given(method("GET"))
+			.and(path("/v1/playlists/1/tracks"))
+			.and(query_param("offset", "0"))
+			.and(query_param("limit", "2"))
+			.respond_with(
+				ResponseTemplate::new(200)
+					.set_body_string(r#"{"items":[{"id":"1"},{"id":"2"}],"total":3}"#),
+			)
+			.expect(1)
+			.mount(&server)
+			.await;
+		Mock::given(method("GET"))
+			.and(path("/v1/playlists/1/tracks"))
+			.and(query_param("offset", "2"))
+			.and(query_param("limit", "2"))
+			.respond_with(
+				ResponseTemplate::new(200).set_body_string(r#"{"items":[{"id":"3"}],"total":3}"#),
+			)
+			.expect(1)
+			.mount(&server)
+			.await;
+
+		let items: Vec<Item> = api(&server)
+			.get_all("/playlists/1/tracks", &[], 2)
+			.await
+			.unwrap();
+		let ids: Vec<&str> = items.iter().map(|i| i.id.as_str()).collect();
+		assert_eq!(ids, ["1", "2", "3"]);
+	}
+}
@@ -0,0 +1,227 @@
+//! Metadata and audio fixtures shared by the integration tests
+//!
+//! `fixtures/audio.ogg` is a 10s beep and `fixtures/audio.mp3` a second of music,
+//! both taken from the examples of rodio.
+
+// Every test binary uses a different part
+#![allow(dead_code)]
+
+use down_on_spot::{
+	Download, DownloadState, Downloader, DownloaderConfig, FileFormat, FixtureMetadata,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Longest time a test waits for its downloads
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Convert JSON of the Web API
+pub fn parse<T: DeserializeOwned>(value: &Value) -> T {
+	serde_json::from_value(value.clone()).unwrap()
+}
+
+fn artist(name: &str) -> Value {
+	let id = name.to_lowercase().replace(' ', "");
+	json!({
+		"external_urls": { "spotify": format!("https://open.spotify.com/artist/{}", id) },
+		"href": format!("https://api.spotify.com/v1/artists/{}", id),
+		"id": id,
+		"name": name,
+		"type": "artist",
+		"uri": format!("spotify:artist:{}", id),
+	})
+}
+
+/// Simplified album as embedded in tracks
+pub fn album(id: &str, name: &str, artists: &[&str], total_tracks: usize) -> Value {
+	json!({
+		"album_type": "album",
+		"artists": artists.iter().map(|a| artist(a)).collect::<Vec<Value>>(),
+		"available_markets": ["US"],
+		"external_urls": { "spotify": format!("https://open.spotify.com/album/{}", id) },
+		"href": format!("https://api.spotify.com/v1/albums/{}", id),
+		"id": id,
+		"images": [],
+		"name": name,
+		"release_date": "2020-05-17",
+		"release_date_precision": "day",
+		"total_tracks": total_tracks,
+		"type": "album",
+		"uri": format!("spotify:album:{}", id),
+	})
+}
+
+/// Full track of album
+pub fn track(id: &str, name: &str, artists: &[&str], album: &Value, number: u32) -> Value {
+	json!({
+		"album": album,
+		"artists": artists.iter().map(|a| artist(a)).collect::<Vec<Value>>(),
+		"available_markets": ["US"],
+		"disc_number": 1,
+		"duration_ms": 10000,
+		"explicit": false,
+		"external_ids": { "isrc": format!("TEST{}", id) },
+		"external_urls": { "spotify": format!("https://open.spotify.com/track/{}", id) },
+		"href": format!("https://api.spotify.com/v1/tracks/{}", id),
+		"id": id,
+		"is_local": false,
+		"name": name,
+		"popularity": 0,
+		"preview_url": null,
+		"track_number": number,
+		"type": "track",
+		"uri": format!("spotify:track:{}", id),
+	})
+}
+
+fn page(items: Vec<Value>) -> Value {
+	json!({
+		"href": "https://api.spotify.com/v1",
+		"limit": items.len().max(1),
+		"next": null,
+		"offset": 0,
+		"previous": null,
+		"total": items.len(),
+		"items": items,
+	})
+}
+
+/// Full album with its tracks
+pub fn full_album(album: &Value, tracks: &[Value]) -> Value {
+	let tracks = tracks
+		.iter()
+		.map(|track| {
+			let mut track = track.clone();
+			for key in ["album", "external_ids", "popularity"] {
+				track.as_object_mut().unwrap().remove(key);
+			}
+			track
+		})
+		.collect();
+	let mut album = album.clone();
+	let fields = album.as_object_mut().unwrap();
+	fields.insert("copyrights".into(), json!([]));
+	fields.insert("external_ids".into(), json!({ "upc": "0000000000000" }));
+	fields.insert("genres".into(), json!(["Test"]));
+	fields.insert("label".into(), json!("Test Records"));
+	fields.insert("popularity".into(), json!(0));
+	fields.insert("tracks".into(), page(tracks));
+	album
+}
+
+/// Playlist of tracks
+pub fn playlist(id: &str, tracks: &[Value]) -> Value {
+	let owner = json!({
+		"display_name": "Owner",
+		"external_urls": { "spotify": "https://open.spotify.com/user/owner" },
+		"href": "https://api.spotify.com/v1/users/owner",
+		"id": "owner",
+		"type": "user",
+		"uri": "spotify:user:owner",
+	});
+	let items = tracks
+		.iter()
+		.map(|track| {
+			json!({
+				"added_at": "2020-05-17T00:00:00Z",
+				"added_by": owner,
+				"is_local": false,
+				"track": track,
+			})
+		})
+		.collect();
+	json!({
+		"collaborative": false,
+		"description": "",
+		"external_urls": { "spotify": format!("https://open.spotify.com/playlist/{}", id) },
+		"followers": { "href": null, "total": 0 },
+		"href": format!("https://api.spotify.com/v1/playlists/{}", id),
+		"id": id,
+		"images": [],
+		"name": "Playlist",
+		"owner": owner,
+		"public": true,
+		"snapshot_id": "1",
+		"tracks": page(items),
+		"type": "playlist",
+		"uri": format!("spotify:playlist:{}", id),
+	})
+}
+
+/// Album `album1` by Artist with tracks `track1` to `track<n>`, the first featuring Guest
+pub fn library(tracks: usize) -> (FixtureMetadata, Vec<Value>) {
+	let album = album("album1", "Album", &["Artist"], tracks);
+	let tracks: Vec<Value> = (1..=tracks)
+		.map(|n| {
+			let artists: &[&str] = match n {
+				1 => &["Artist", "Guest"],
+				_ => &["Artist"],
+			};
+			let id = format!("track{}", n);
+			track(&id, &format!("Track {}", n), artists, &album, n as u32)
+		})
+		.collect();
+	let mut metadata = FixtureMetadata::new();
+	metadata.add_album(parse(&full_album(&album, &tracks)));
+	for track in &tracks {
+		metadata.add_track(parse(track));
+	}
+	(metadata, tracks)
+}
+
+/// Empty directory in the system temp directory, unique per test
+pub fn temp_dir(name: &str) -> PathBuf {
+	let path = std::env::temp_dir().join(format!("down_on_spot_{}_{}", name, std::process::id()));
+	std::fs::remove_dir_all(&path).ok();
+	std::fs::create_dir_all(&path).unwrap();
+	path
+}
+
+/// Serve the fixture audio for track in formats, laid out for `LocalAudioSource`
+pub fn audio(dir: &Path, track_id: &str, formats: &[FileFormat]) {
+	let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+	std::fs::create_dir_all(dir.join(track_id)).unwrap();
+	for format in formats {
+		let name = format!("{:?}", format);
+		let extension = match name.starts_with("OGG") {
+			true => "ogg",
+			false => "mp3",
+		};
+		std::fs::copy(
+			fixtures.join(format!("audio.{}", extension)),
+			dir.join(track_id).join(format!("{}.{}", name, extension)),
+		)
+		.unwrap();
+	}
+}
+
+/// Settings downloading to `<dir>/<album artist>/<album>/<track> - <title>`
+pub fn config(dir: &Path) -> DownloaderConfig {
+	let mut config = DownloaderConfig::new();
+	config.path = dir
+		.join("%albumArtist%")
+		.join("%album%")
+		.to_str()
+		.unwrap()
+		.to_string();
+	config.filename_template = "%0track% - %title%".to_string();
+	config
+}
+
+/// Wait until every download is done or failed
+pub async fn finish(downloader: &Downloader) -> Vec<Download> {
+	let start = Instant::now();
+	loop {
+		let downloads = downloader.get_downloads().await;
+		let finished = downloads
+			.iter()
+			.all(|d| matches!(d.state, DownloadState::Done | DownloadState::Error(_)));
+		if !downloads.is_empty() && finished {
+			return downloads;
+		}
+		assert!(start.elapsed() < TIMEOUT, "Downloads not finished");
+		tokio::time::sleep(Duration::from_millis(50)).await;
+	}
+}
@@ -0,0 +1,189 @@
+//! Downloading from local audio, checking the written files and their tags
+
+mod common;
+
+use down_on_spot::tag::{Field, Tag, TagWrap};
+use down_on_spot::{
+	ArtistsTag, AudioFormat, Download, DownloadEvent, DownloadState, Downloader, DownloaderConfig,
+	FileFormat, LocalAudioSource, Quality, SpotifyError, VerifyMode,
+};
+use futures::{FutureExt, StreamExt};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use common::{audio, config, finish, library, temp_dir};
+
+/// Download the first track of the fixture library with config, serving audio in formats
+async fn download(dir: &Path, config: DownloaderConfig, formats: &[FileFormat]) -> Download {
+	let (metadata, _) = library(1);
+	audio(&dir.join("audio"), "track1", formats);
+	let downloader = Downloader::with_sources(
+		config,
+		Arc::new(metadata),
+		Arc::new(LocalAudioSource::new(dir.join("audio"))),
+	);
+	downloader.add_uri("spotify:track:track1").await.unwrap();
+	finish(&downloader).await.remove(0)
+}
+
+/// Path of the first track, see `common::config`
+fn track_path(dir: &Path, extension: &str) -> PathBuf {
+	dir.join("Artist")
+		.join("Album")
+		.join(format!("01 - Track 1.{}", extension))
+}
+
+/// Check the tags every download gets
+fn assert_tags(tag: &dyn Tag) {
+	assert_eq!(tag.get_field(Field::Title), ["Track 1"]);
+	assert_eq!(tag.get_field(Field::Album), ["Album"]);
+	assert_eq!(tag.get_field(Field::AlbumArtist), ["Artist"]);
+	assert_eq!(tag.get_field(Field::TrackNumber), ["1"]);
+	assert_eq!(tag.get_unique_file_identifier().as_deref(), Some("track1"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn falls_back_to_lower_quality() {
+	let dir = temp_dir("falls_back_to_lower_quality");
+	let mut config = config(&dir);
+	config.quality = Quality::Q320;
+	// 160kbps MP3 is preferred over 96kbps Ogg
+	let download = download(
+		&dir,
+		config,
+		&[FileFormat::OGG_VORBIS_96, FileFormat::MP3_160],
+	)
+	.await;
+
+	assert_eq!(download.state, DownloadState::Done);
+	let path = track_path(&dir, "mp3");
+	assert_eq!(download.path.as_deref(), Some(path.as_path()));
+	assert!(!track_path(&dir, "ogg").exists());
+	let tag_wrap = TagWrap::new(&path, AudioFormat::Mp3).unwrap();
+	assert_tags(tag_wrap.tag());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fails_without_any_quality() {
+	let dir = temp_dir("fails_without_any_quality");
+	let download = download(&dir, config(&dir), &[]).await;
+
+	assert_eq!(
+		download.state,
+		DownloadState::Error(SpotifyError::Unavailable)
+	);
+	assert!(download.path.is_none());
+	assert!(!track_path(&dir, "ogg").exists());
+	assert!(!track_path(&dir, "mp3").exists());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn converts_to_mp3() {
+	let dir = temp_dir("converts_to_mp3");
+	let mut config = config(&dir);
+	config.convert_to_mp3 = true;
+	// Decodes the whole file and compares it to the track duration
+	config.verify = VerifyMode::Full;
+	let download = download(&dir, config, &[FileFormat::OGG_VORBIS_320]).await;
+
+	assert_eq!(download.state, DownloadState::Done);
+	let path = track_path(&dir, "mp3");
+	assert_eq!(download.path.as_deref(), Some(path.as_path()));
+	assert!(!track_path(&dir, "ogg").exists());
+	let data = std::fs::read(&path).unwrap();
+	assert_eq!(&data[0..3], b"ID3");
+	let tag_wrap = TagWrap::new(&path, AudioFormat::Mp3).unwrap();
+	assert_tags(tag_wrap.tag());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reports_source_progress_when_converting() {
+	let dir = temp_dir("reports_source_progress_when_converting");
+	let (metadata, _) = library(1);
+	audio(&dir.join("audio"), "track1", &[FileFormat::OGG_VORBIS_320]);
+	let mut config = config(&dir);
+	config.convert_to_mp3 = true;
+	let downloader = Downloader::with_sources(
+		config,
+		Arc::new(metadata),
+		Arc::new(LocalAudioSource::new(dir.join("audio"))),
+	);
+	let mut events = downloader.subscribe();
+	downloader.add_uri("spotify:track:track1").await.unwrap();
+	finish(&downloader).await;
+
+	let mut progress = None;
+	while let Some(Some(event)) = events.next().now_or_never() {
+		if let DownloadEvent::Progress(_, read, total) = event {
+			assert!(read <= total, "read {} of {}", read, total);
+			progress = Some((read, total));
+		}
+	}
+	// The MP3 is larger than the Ogg source here
+	let size = std::fs::metadata(dir.join("audio/track1/OGG_VORBIS_320.ogg"))
+		.unwrap()
+		.len() as usize;
+	assert_eq!(progress, Some((size, size)));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writes_ogg_tags() {
+	let dir = temp_dir("writes_ogg_tags");
+	let download = download(&dir, config(&dir), &[FileFormat::OGG_VORBIS_320]).await;
+
+	assert_eq!(download.state, DownloadState::Done);
+	let tag_wrap = TagWrap::new(track_path(&dir, "ogg"), AudioFormat::Ogg).unwrap();
+	let tag = tag_wrap.tag();
+	assert_tags(tag);
+	assert_eq!(tag.get_field(Field::Artist), ["Artist", "Guest"]);
+	assert_eq!(tag.get_field(Field::Genre), ["Test"]);
+	assert_eq!(tag.get_field(Field::Label), ["Test Records"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn writes_id3_tags() {
+	let dir = temp_dir("writes_id3_tags");
+	let mut config = config(&dir);
+	config.artists_tag = ArtistsTag::JoinedWithArtists;
+	let download = download(&dir, config, &[FileFormat::MP3_320]).await;
+
+	assert_eq!(download.state, DownloadState::Done);
+	let tag_wrap = TagWrap::new(track_path(&dir, "mp3"), AudioFormat::Mp3).unwrap();
+	let tag = tag_wrap.tag();
+	assert_tags(tag);
+	assert_eq!(tag.get_field(Field::Artist), ["Artist, Guest"]);
+	assert_eq!(tag.get_field(Field::Artists), ["Artist", "Guest"]);
+	assert_eq!(tag.get_field(Field::Genre), ["Test"]);
+	assert_eq!(tag.get_field(Field::Label), ["Test Records"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn skips_existing_files() {
+	let dir = temp_dir("skips_existing_files");
+	let path = track_path(&dir, "ogg");
+	let download_once = || download(&dir, config(&dir), &[FileFormat::OGG_VORBIS_320]);
+	assert_eq!(download_once().await.state, DownloadState::Done);
+
+	// Changed tags show whether the file was written again
+	let mut tag_wrap = TagWrap::new(&path, AudioFormat::Ogg).unwrap();
+	tag_wrap
+		.get_tag()
+		.set_field(Field::Title, vec!["Changed".to_string()]);
+	tag_wrap.get_tag().save().unwrap();
+
+	let skipped = download_once().await;
+	assert_eq!(
+		skipped.state,
+		DownloadState::Error(SpotifyError::AlreadyDownloaded)
+	);
+	let tag_wrap = TagWrap::new(&path, AudioFormat::Ogg).unwrap();
+	assert_eq!(tag_wrap.tag().get_field(Field::Title), ["Changed"]);
+
+	// Overwritten if disabled
+	let mut config = config(&dir);
+	config.skip_existing = false;
+	let overwritten = download(&dir, config, &[FileFormat::OGG_VORBIS_320]).await;
+	assert_eq!(overwritten.state, DownloadState::Done);
+	let tag_wrap = TagWrap::new(&path, AudioFormat::Ogg).unwrap();
+	assert_tags(tag_wrap.tag());
+}
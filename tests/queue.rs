@@ -0,0 +1,121 @@
+//! Queueing URIs and rendering paths and tags, without network
+
+mod common;
+
+use down_on_spot::tag::{Field, TagWrap};
+use down_on_spot::{
+	AudioFormat, DownloadState, Downloader, FileFormat, FixtureMetadata, LocalAudioSource,
+};
+use std::path::Path;
+use std::sync::Arc;
+
+use common::{config, finish, library, parse, playlist, temp_dir};
+
+/// Downloader serving the audio in `<dir>/audio`
+fn downloader(metadata: FixtureMetadata, dir: &Path) -> Downloader {
+	Downloader::with_sources(
+		config(dir),
+		Arc::new(metadata),
+		Arc::new(LocalAudioSource::new(dir.join("audio"))),
+	)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn queues_track() {
+	let dir = temp_dir("queues_track");
+	let (metadata, _) = library(2);
+	let downloader = downloader(metadata, &dir);
+	downloader
+		.add_uri("https://open.spotify.com/track/track2")
+		.await
+		.unwrap();
+
+	let downloads = downloader.get_downloads().await;
+	assert_eq!(downloads.len(), 1);
+	assert_eq!(downloads[0].track_id, "track2");
+	assert_eq!(downloads[0].title, "Track 2");
+	assert_eq!(downloads[0].album_id.as_deref(), Some("album1"));
+	assert_eq!(downloads[0].source.as_deref(), Some("spotify:track:track2"));
+	assert!(!downloads[0].whole_album);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn queues_album() {
+	let dir = temp_dir("queues_album");
+	let (metadata, _) = library(3);
+	let downloader = downloader(metadata, &dir);
+	downloader.add_uri("spotify:album:album1").await.unwrap();
+
+	let downloads = downloader.get_downloads().await;
+	let ids: Vec<&str> = downloads.iter().map(|d| d.track_id.as_str()).collect();
+	assert_eq!(ids, ["track1", "track2", "track3"]);
+	for download in &downloads {
+		assert_eq!(download.album_id.as_deref(), Some("album1"));
+		assert_eq!(download.source.as_deref(), Some("spotify:album:album1"));
+		assert!(download.whole_album);
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn queues_every_playlist_track() {
+	let dir = temp_dir("queues_every_playlist_track");
+	// More than one page of 100 tracks, `full_playlist` pages like the Web API
+	let (mut metadata, tracks) = library(150);
+	metadata.add_playlist(parse(&playlist("playlist1", &tracks)));
+	let downloader = downloader(metadata, &dir);
+	downloader
+		.add_uri("spotify:playlist:playlist1")
+		.await
+		.unwrap();
+
+	let downloads = downloader.get_downloads().await;
+	assert_eq!(downloads.len(), 150);
+	for (n, download) in downloads.iter().enumerate() {
+		assert_eq!(download.track_id, format!("track{}", n + 1));
+		assert_eq!(download.id, n as i64);
+		assert_eq!(
+			download.source.as_deref(),
+			Some("spotify:playlist:playlist1")
+		);
+		assert!(!download.whole_album);
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejects_unknown_uri() {
+	let dir = temp_dir("rejects_unknown_uri");
+	let (metadata, _) = library(1);
+	let downloader = downloader(metadata, &dir);
+	assert!(downloader.add_uri("spotify:album:missing").await.is_err());
+	assert!(downloader
+		.add_uri("https://example.com/track/1")
+		.await
+		.is_err());
+	assert!(downloader.get_downloads().await.is_empty());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn renders_path_and_writes_tags() {
+	let dir = temp_dir("renders_path_and_writes_tags");
+	let (metadata, _) = library(1);
+	common::audio(&dir.join("audio"), "track1", &[FileFormat::OGG_VORBIS_320]);
+	let downloader = downloader(metadata, &dir);
+	downloader.add_uri("spotify:track:track1").await.unwrap();
+
+	let downloads = finish(&downloader).await;
+	assert_eq!(downloads[0].state, DownloadState::Done);
+	let path = dir.join("Artist").join("Album").join("01 - Track 1.ogg");
+	assert_eq!(downloads[0].path.as_deref(), Some(path.as_path()));
+
+	let tag_wrap = TagWrap::new(&path, AudioFormat::Ogg).unwrap();
+	let tag = tag_wrap.tag();
+	assert_eq!(tag.get_field(Field::Title), ["Track 1"]);
+	assert_eq!(tag.get_field(Field::Artist), ["Artist", "Guest"]);
+	assert_eq!(tag.get_field(Field::Album), ["Album"]);
+	assert_eq!(tag.get_field(Field::AlbumArtist), ["Artist"]);
+	assert_eq!(tag.get_field(Field::TrackNumber), ["1"]);
+	assert_eq!(tag.get_field(Field::DiscNumber), ["1"]);
+	assert_eq!(tag.get_field(Field::Genre), ["Test"]);
+	assert_eq!(tag.get_field(Field::Label), ["Test Records"]);
+	assert_eq!(tag.get_unique_file_identifier().as_deref(), Some("track1"));
+}
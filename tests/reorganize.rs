@@ -0,0 +1,69 @@
+//! Planning moves of already downloaded files
+
+mod common;
+
+use down_on_spot::tag::{Field, TagWrap};
+use down_on_spot::{AudioFormat, Reorganizer};
+use std::path::Path;
+
+use common::{config, temp_dir};
+
+/// Copy the fixture Ogg to path and tag it as track of album by artist
+fn tagged(path: &Path, artist: &str, album: &str, track: u32) {
+	let fixture = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/audio.ogg");
+	std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+	std::fs::copy(fixture, path).unwrap();
+
+	let mut tag_wrap = TagWrap::new(path, AudioFormat::Ogg).unwrap();
+	let tag = tag_wrap.get_tag();
+	tag.set_field(Field::Title, vec![format!("Track {}", track)]);
+	tag.set_field(Field::Artist, vec![artist.to_string()]);
+	tag.set_field(Field::AlbumArtist, vec![artist.to_string()]);
+	tag.set_field(Field::Album, vec![album.to_string()]);
+	tag.set_field(Field::TrackNumber, vec![track.to_string()]);
+	tag.add_unique_file_identifier(&format!("track{}", track));
+	tag.save().unwrap();
+}
+
+#[test]
+fn keeps_separator_in_artist_name() {
+	let dir = temp_dir("keeps_separator_in_artist_name");
+	// Already where the downloader puts it, the separator is ", "
+	tagged(
+		&dir.join("Tyler, The Creator/Album/01 - Track 1.ogg"),
+		"Tyler, The Creator",
+		"Album",
+		1,
+	);
+
+	let plan = Reorganizer::new(config(&dir)).plan(&dir).unwrap();
+	assert!(plan.errors.is_empty(), "{:?}", plan.errors);
+	assert!(plan.collisions.is_empty(), "{:?}", plan.collisions);
+	assert!(plan.moves.is_empty(), "{:?}", plan.moves);
+}
+
+#[test]
+fn reports_colliding_covers() {
+	let dir = temp_dir("reports_colliding_covers");
+	// Both directories end up in Artist/Album, each with its own cover
+	for (directory, track) in [("a", 1), ("b", 2)] {
+		tagged(
+			&dir.join(directory).join(format!("{}.ogg", track)),
+			"Artist",
+			"Album",
+			track,
+		);
+		std::fs::write(dir.join(directory).join("cover.jpg"), directory).unwrap();
+	}
+
+	let plan = Reorganizer::new(config(&dir)).plan(&dir).unwrap();
+	assert_eq!(plan.collisions.len(), 1, "{:?}", plan.collisions);
+	let collision = &plan.collisions[0];
+	assert!(collision.target.ends_with("Artist/Album/cover.jpg"));
+	assert_eq!(collision.sources.len(), 2);
+	// The tracks are still moved
+	let targets: Vec<&Path> = plan.moves.iter().map(|m| m.to.as_path()).collect();
+	assert_eq!(targets.len(), 2, "{:?}", targets);
+	assert!(targets[0].ends_with("Artist/Album/01 - Track 1.ogg"));
+	assert!(targets[1].ends_with("Artist/Album/02 - Track 2.ogg"));
+}